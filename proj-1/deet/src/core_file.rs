@@ -0,0 +1,195 @@
+//! Post-mortem access to the registers and memory of a crashed program through its ELF core dump
+
+use crate::dwarf_data::read_unsigned;
+use crate::inferior::{Breakpoint, ProcessState};
+use crate::link_map::auxv_entry;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+const PT_LOAD: u64 = 1;
+const PT_NOTE: u64 = 4;
+const ET_CORE: u64 = 4;
+const NT_PRSTATUS: u64 = 1;
+const NT_PRFPREG: u64 = 2;
+const NT_AUXV: u64 = 6;
+
+/// Offsets into the x86-64 `struct elf_prstatus`
+const PRSTATUS_CURSIG_OFFSET: usize = 12;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_REGS_OFFSET: usize = 112;
+
+/// A piece of the crashed program's address space
+struct Segment {
+    addr: usize,
+    data: Vec<u8>,
+}
+
+struct ProgramHeader {
+    p_type: u64,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+}
+
+pub struct CoreFile {
+    /// Signal that killed the process
+    pub signal: i32,
+    pub pid: i32,
+    /// Entry point of the executable as it was loaded, from the auxiliary vector
+    pub entry: Option<usize>,
+    regs: libc::user_regs_struct,
+    fpregs: Option<libc::user_fpregs_struct>,
+    /// Memory dumped into the core, followed by the executable's own segments. The kernel does
+    /// not dump unmodified file-backed mappings such as the program's code, so those are read
+    /// from the executable instead.
+    segments: Vec<Segment>,
+}
+
+impl CoreFile {
+    /// Loads the core dump at `core_path` written by a process running `executable_path`
+    pub fn load(core_path: &str, executable_path: &str) -> Result<CoreFile, String> {
+        let core = std::fs::read(core_path).map_err(|e| format!("{}: {}", core_path, e))?;
+        let headers = program_headers(&core, Some(ET_CORE))
+            .map_err(|message| format!("{}: {}", core_path, message))?;
+
+        let mut prstatus = None;
+        let mut fpregs = None;
+        let mut entry = None;
+        for header in headers.iter().filter(|header| header.p_type == PT_NOTE) {
+            let notes = file_slice(&core, header.offset, header.filesz)
+                .ok_or_else(|| format!("{}: truncated note segment", core_path))?;
+            for (note_type, desc) in parse_notes(notes) {
+                // Only the first thread is looked at, which is the one that crashed
+                if note_type == NT_PRSTATUS && prstatus.is_none() {
+                    prstatus = Some(desc);
+                } else if note_type == NT_PRFPREG && fpregs.is_none() {
+                    fpregs = read_struct::<libc::user_fpregs_struct>(desc, 0);
+                } else if note_type == NT_AUXV {
+                    entry = auxv_entry(desc);
+                }
+            }
+        }
+        let prstatus = prstatus.ok_or_else(|| format!("{}: no NT_PRSTATUS note", core_path))?;
+        let regs = read_struct::<libc::user_regs_struct>(prstatus, PRSTATUS_REGS_OFFSET)
+            .ok_or_else(|| format!("{}: truncated NT_PRSTATUS note", core_path))?;
+
+        let mut segments = load_segments(&core, &headers);
+        if let Ok(executable) = std::fs::read(executable_path) {
+            if let Ok(headers) = program_headers(&executable, None) {
+                segments.extend(load_segments(&executable, &headers));
+            }
+        }
+
+        Ok(CoreFile {
+            signal: read_unsigned(&prstatus[PRSTATUS_CURSIG_OFFSET..PRSTATUS_CURSIG_OFFSET + 2])
+                as i32,
+            pid: read_unsigned(&prstatus[PRSTATUS_PID_OFFSET..PRSTATUS_PID_OFFSET + 4]) as i32,
+            entry,
+            regs,
+            fpregs,
+            segments,
+        })
+    }
+}
+
+impl ProcessState for CoreFile {
+    fn getregs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        Ok(self.regs)
+    }
+
+    fn getfpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        self.fpregs.ok_or(nix::Error::Sys(nix::errno::Errno::ENODATA))
+    }
+
+    fn read_memory(&self, addr: usize, len: usize, _breakpoints: &HashMap<usize, Breakpoint>) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let curr_addr = addr + bytes.len();
+            let segment = self
+                .segments
+                .iter()
+                .find(|segment| {
+                    segment.addr <= curr_addr && curr_addr < segment.addr + segment.data.len()
+                })
+                .ok_or(nix::Error::Sys(nix::errno::Errno::EIO))?;
+            let start = curr_addr - segment.addr;
+            let end = segment.data.len().min(start + len - bytes.len());
+            bytes.extend_from_slice(&segment.data[start..end]);
+        }
+        Ok(bytes)
+    }
+}
+
+fn file_slice(file: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    file.get(offset..offset.checked_add(len)?)
+}
+
+/// Copies a C struct out of `bytes` at `offset`
+fn read_struct<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    let bytes = file_slice(bytes, offset, size_of::<T>())?;
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// Parses the program header table of a little-endian ELF64 file, optionally checking its
+/// e_type
+fn program_headers(file: &[u8], expected_type: Option<u64>) -> Result<Vec<ProgramHeader>, String> {
+    let field = |offset: usize, len: usize| file_slice(file, offset, len).map(read_unsigned);
+    if file.get(..4) != Some(b"\x7fELF") || file.get(4) != Some(&2) || file.get(5) != Some(&1) {
+        return Err("not a 64-bit little-endian ELF file".to_string());
+    }
+    if expected_type.is_some() && field(0x10, 2) != expected_type {
+        return Err("not a core dump".to_string());
+    }
+    let truncated = || "truncated ELF header".to_string();
+    let phoff = field(0x20, 8).ok_or_else(truncated)? as usize;
+    let phentsize = field(0x36, 2).ok_or_else(truncated)? as usize;
+    let phnum = field(0x38, 2).ok_or_else(truncated)? as usize;
+    (0..phnum)
+        .map(|i| {
+            let header = file_slice(file, phoff + i * phentsize, phentsize)
+                .filter(|header| header.len() >= 0x28)
+                .ok_or_else(|| "truncated program header".to_string())?;
+            Ok(ProgramHeader {
+                p_type: read_unsigned(&header[0..4]),
+                offset: read_unsigned(&header[0x08..0x10]) as usize,
+                vaddr: read_unsigned(&header[0x10..0x18]) as usize,
+                filesz: read_unsigned(&header[0x20..0x28]) as usize,
+            })
+        })
+        .collect()
+}
+
+/// Returns the contents of the PT_LOAD segments that are present in the file
+fn load_segments(file: &[u8], headers: &[ProgramHeader]) -> Vec<Segment> {
+    headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD && header.filesz > 0)
+        .filter_map(|header| {
+            Some(Segment {
+                addr: header.vaddr,
+                data: file_slice(file, header.offset, header.filesz)?.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Splits a PT_NOTE segment into (type, descriptor) pairs. Names and descriptors are padded to
+/// 4 bytes.
+fn parse_notes(mut notes: &[u8]) -> Vec<(u64, &[u8])> {
+    let align = |len: usize| (len + 3) & !3;
+    let mut parsed = Vec::new();
+    while notes.len() >= 12 {
+        let namesz = read_unsigned(&notes[0..4]) as usize;
+        let descsz = read_unsigned(&notes[4..8]) as usize;
+        let note_type = read_unsigned(&notes[8..12]);
+        let desc_start = 12 + align(namesz);
+        let desc = match file_slice(notes, desc_start, descsz) {
+            Some(desc) => desc,
+            None => break,
+        };
+        parsed.push((note_type, desc));
+        notes = &notes[(desc_start + align(descsz)).min(notes.len())..];
+    }
+    parsed
+}
+
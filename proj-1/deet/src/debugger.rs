@@ -1,36 +1,273 @@
 
+use crate::core_file::CoreFile;
 use crate::debugger_command::DebuggerCommand;
+use crate::disassembler::{self, DecodedInstruction, MAX_INSTRUCTION_LENGTH};
 use crate::inferior::Inferior;
 use crate::inferior::Status;
-use crate::inferior::Breakpoint;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::inferior::{signal_description, CatchEvent, CatchPolicy, ForkPolicy, SignalPolicy};
+use crate::inferior::{register_value, Breakpoint, Frame, ProcessState, WatchAccess, REGISTER_NAMES, WATCH_SLOTS};
+use crate::dwarf_data::{
+    format_char, format_float, format_string, read_float, read_signed, read_unsigned, DwarfData,
+    Encoding, Error as DwarfError, File, Function, Line, Location, Type, TypeKind,
+};
+use crate::expr::{self, Evaluator, Value};
+use crate::link_map::{process_entry, read_link_map, SharedLibrary};
+use crate::syscalls::{self, ARGUMENT_REGISTERS};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 // use nix::sys::wait::WaitPidFlag;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// `x/s` stops reading a string without a NUL after this many bytes
+const MAX_EXAMINE_STRING_LENGTH: usize = 200;
 
 pub struct Debugger {
     target: String,
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
+    /// Core dump being examined when there is no live inferior
+    core: Option<CoreFile>,
     dwarf_data: DwarfData,
-    breakpoints: Vec<usize>,
+    breakpoints: Vec<UserBreakpoint>,
+    next_breakpoint_number: usize,
     breakpoints_map: HashMap<usize, Breakpoint>,
-    stopped_rip: usize,
+    watchpoints: Vec<Watchpoint>,
+    /// Number of the watchpoint that each programmed debug register belongs to
+    watch_slots: Vec<usize>,
+    catchpoints: Vec<Catchpoint>,
+    /// Whether every system call is logged, as set with `trace syscalls`
+    trace_syscalls: bool,
+    /// System calls being traced that have not returned yet
+    syscall_entries: Vec<SyscallEntry>,
+    /// Frame that print, info locals and info args look at, counted from the innermost one
+    selected_frame: usize,
+    /// Shared libraries mapped into the inferior or the core dump
+    libraries: Vec<SharedLibrary>,
+    /// Entry point of a freshly started inferior that has not reached it yet. The shared
+    /// libraries are mapped by then, so the remaining breakpoints are installed there.
+    entry_trap: Option<usize>,
+    /// The dynamic loader's r_brk hook, which it calls whenever it maps or unmaps a library
+    library_trap: Option<usize>,
+    /// What to do with each signal the inferior receives, as set with `handle`
+    signal_policies: HashMap<Signal, SignalPolicy>,
+    /// Which process is debugged after a fork, as set with `set follow-fork-mode` and `set
+    /// detach-on-fork`
+    fork_policy: ForkPolicy,
+    /// Where `until` and `advance` are headed. They are forgotten as soon as the inferior stops.
+    one_shots: Vec<OneShot>,
+    /// Format letter and unit size `x` used last
+    examine_format: char,
+    examine_size: usize,
+    /// Where a bare `x` carries on from
+    next_examine: Option<usize>,
+}
+
+/// A numbered breakpoint as the user sees it. Several of them may share an address; the 0xcc
+/// itself is tracked in `Debugger::breakpoints_map` for as long as one of them is enabled.
+struct UserBreakpoint {
+    number: usize,
+    addr: usize,
+    /// The location as the user gave it, for setting the breakpoint again in another program
+    location: String,
+    enabled: bool,
+    hit_count: usize,
+    /// Source text of the `if` expression; the breakpoint only stops when it is nonzero
+    condition: Option<String>,
+    /// Number of upcoming hits to pass over without stopping
+    ignore_count: usize,
+    /// Set by tbreak: the breakpoint is deleted the first time it stops the inferior
+    temporary: bool,
+}
+
+/// An unnumbered breakpoint that stops the inferior once, for `until` and `advance`. Like the
+/// entry and library traps, it shares the 0xcc in `Debugger::breakpoints_map` with any user
+/// breakpoint at the same address. Only the thread it was set for stops there.
+struct OneShot {
+    addr: usize,
+    thread: Pid,
+    /// If set, only stop once %rsp is at least this high, i.e. when the frame that ends here
+    /// has returned. Recursive calls passing the same address are ignored.
+    frame_end: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+impl WatchKind {
+    fn describe(self) -> &'static str {
+        match self {
+            WatchKind::Write => "Hardware watchpoint",
+            WatchKind::Read => "Hardware read watchpoint",
+            WatchKind::Access => "Hardware access (read/write) watchpoint",
+        }
+    }
+}
+
+/// A numbered hardware watchpoint, sharing its numbering with the breakpoints. A watched object
+/// that is not aligned or not 1, 2, 4 or 8 bytes long takes up several debug registers.
+struct Watchpoint {
+    number: usize,
+    kind: WatchKind,
+    expr: String,
+    addr: usize,
+    entity_type: Type,
+    /// The value last seen, so that changes can be reported. None if it could not be read.
+    old_value: Option<Vec<u8>>,
+    enabled: bool,
+    hit_count: usize,
+    /// Set if the watched object lives in a stack frame, which the watchpoint goes away with
+    scope: Option<WatchScope>,
+}
+
+/// The stack frame a watched local variable lives in. A trap at the return address notices
+/// when the frame returns, as gdb does.
+struct WatchScope {
+    thread: Pid,
+    /// The frame's CFA, which %rsp is back at once the frame has returned
+    frame_end: usize,
+    return_addr: usize,
+}
+
+/// What a catchpoint stops at
+enum CatchKind {
+    /// The system calls with these numbers, or every one if there are none
+    Syscall(Vec<u64>),
+    /// These signals, or if there are none, every signal but SIGTRAP and SIGINT, which the
+    /// debugger uses itself
+    Signal(Vec<Signal>),
+    /// Every signal, SIGINT included
+    AnySignal,
+    Fork,
+    Vfork,
+    Exec,
+}
+
+impl CatchKind {
+    /// Describes the catchpoint as it is announced when set, e.g. `syscall 'write' [1]`
+    fn describe(&self) -> String {
+        let syscall = |number: &u64| match syscalls::syscall_name(*number) {
+            Some(name) => format!("'{}' [{}]", name, number),
+            None => number.to_string(),
+        };
+        let signal_names = |signals: &[Signal]| {
+            signals.iter().map(|sig| sig.as_ref()).collect::<Vec<&str>>().join(" ")
+        };
+        match self {
+            CatchKind::Syscall(numbers) => match numbers.as_slice() {
+                [] => "any syscall".to_string(),
+                [number] => format!("syscall {}", syscall(number)),
+                _ => format!("syscalls {}", numbers.iter().map(syscall).collect::<Vec<String>>().join(" ")),
+            },
+            CatchKind::Signal(signals) => match signals.as_slice() {
+                [] => "standard signals".to_string(),
+                [sig] => format!("signal {}", sig),
+                _ => format!("signals {}", signal_names(signals)),
+            },
+            CatchKind::AnySignal => "any signal".to_string(),
+            CatchKind::Fork => "fork".to_string(),
+            CatchKind::Vfork => "vfork".to_string(),
+            CatchKind::Exec => "exec".to_string(),
+        }
+    }
+
+    /// The signals this catchpoint stops at
+    fn signals(&self) -> Vec<Signal> {
+        match self {
+            CatchKind::Signal(signals) if signals.is_empty() => Signal::iterator()
+                .filter(|sig| *sig != Signal::SIGTRAP && *sig != Signal::SIGINT)
+                .collect(),
+            CatchKind::Signal(signals) => signals.clone(),
+            CatchKind::AnySignal => Signal::iterator().filter(|sig| *sig != Signal::SIGTRAP).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Tells whether the inferior stopping with `status` triggers this catchpoint
+    fn matches(&self, status: &Status) -> bool {
+        match (self, status) {
+            (CatchKind::Syscall(numbers), Status::Caught(CatchEvent::SyscallEntry(number), _))
+            | (CatchKind::Syscall(numbers), Status::Caught(CatchEvent::SyscallExit(number), _)) => {
+                numbers.is_empty() || numbers.contains(number)
+            }
+            (_, Status::Stopped(sig, _)) => *sig != Signal::SIGTRAP && self.signals().contains(sig),
+            (CatchKind::Fork, Status::Caught(CatchEvent::Fork(_), _))
+            | (CatchKind::Vfork, Status::Caught(CatchEvent::Vfork(_), _))
+            | (CatchKind::Exec, Status::Execed) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A numbered catchpoint, sharing its numbering with the breakpoints
+struct Catchpoint {
+    number: usize,
+    kind: CatchKind,
+    enabled: bool,
+    hit_count: usize,
+}
+
+/// A traced system call that has not returned yet
+struct SyscallEntry {
+    tid: Pid,
+    number: u64,
+    /// The call as it is logged, without its return value
+    call: String,
+    /// The code it returned with to be restarted, if something interrupted it
+    restart: Option<u64>,
+}
+
+/// Splits `len` bytes at `addr` into the aligned 1, 2, 4 or 8 byte ranges a debug register
+/// can watch
+fn watch_chunks(mut addr: usize, len: usize) -> Vec<(usize, usize)> {
+    let end = addr + len;
+    let mut chunks = Vec::new();
+    while addr < end {
+        let size = [8, 4, 2, 1]
+            .iter()
+            .cloned()
+            .find(|size| addr & (size - 1) == 0 && addr + size <= end)
+            .unwrap();
+        chunks.push((addr, size));
+        addr += size;
+    }
+    chunks
+}
+
+fn load_dwarf_data(target: &str) -> Result<DwarfData, String> {
+    match DwarfData::from_file(target) {
+        Ok(val) => Ok(val),
+        Err(DwarfError::ErrorOpeningFile) => Err(format!("Could not open file {}", target)),
+        Err(DwarfError::DwarfFormatError(err)) => Err(format!(
+            "Could not debugging symbols from {}: {:?}",
+            target, err
+        )),
+    }
+}
+
+/// Returns the path of the executable a process is running, as found through /proc
+pub fn process_executable(pid: i32) -> String {
+    let link = format!("/proc/{}/exe", pid);
+    match std::fs::read_link(&link) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => link,
+    }
 }
 
 impl Debugger {
     /// Initializes the debugger.
     pub fn new(target: &str) -> Debugger {
-        let debug_data = match DwarfData::from_file(target) {
+        let debug_data = match load_dwarf_data(target) {
             Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
-            }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not debugging symbols from {}: {:?}", target, err);
+            Err(message) => {
+                println!("{}", message);
                 std::process::exit(1);
             }
         };
@@ -47,32 +284,47 @@ impl Debugger {
             history_path,
             readline,
             inferior: None,
+            core: None,
             dwarf_data: debug_data,
             breakpoints: Vec::new(),
+            next_breakpoint_number: 0,
             breakpoints_map: HashMap::new(),
-            stopped_rip: 0 as usize,
+            watchpoints: Vec::new(),
+            watch_slots: Vec::new(),
+            catchpoints: Vec::new(),
+            trace_syscalls: false,
+            syscall_entries: Vec::new(),
+            selected_frame: 0,
+            libraries: Vec::new(),
+            entry_trap: None,
+            library_trap: None,
+            one_shots: Vec::new(),
+            signal_policies: Signal::iterator()
+                .map(|sig| (sig, SignalPolicy::default_for(sig)))
+                .collect(),
+            fork_policy: ForkPolicy::default(),
+            examine_format: 'x',
+            examine_size: 4,
+            next_examine: None,
         }
     }
 
     pub fn run(&mut self) {
         loop {
-            match self.get_next_command() {
+            let cmd = self.get_next_command();
+            match cmd {
                 DebuggerCommand::Run(args) => {
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &self.breakpoints, &mut self.breakpoints_map) {
-                        // Check existed inferior and kill it
-                        if self.inferior.is_some() {
-                            self.inferior.as_mut().unwrap().kill();
-                        }
-                        // Create the inferior
-                        self.inferior = Some(inferior);
+                    if self.start_inferior(&args) {
                         self.continue_exec();
-                    } else {
-                        println!("Error starting subprocess");
                     }
                 }
                 DebuggerCommand::Quit => {
                     if self.inferior.is_some() {
-                        self.inferior.as_mut().unwrap().kill();
+                        if self.inferior.as_ref().unwrap().is_attached() {
+                            self.detach();
+                        } else {
+                            self.inferior.as_mut().unwrap().kill();
+                        }
                     }
                     return;
                 }
@@ -82,200 +334,2766 @@ impl Debugger {
                         println!("The program is not being run.");
                         continue;
                     }
+                    self.continue_exec();
+                }
+                DebuggerCommand::Step | DebuggerCommand::Next => {
+                    let step_into = matches!(cmd, DebuggerCommand::Step);
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    let rip = match self.inferior.as_ref().unwrap().getregs() {
+                        Ok(regs) => regs.rip as usize,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    let library_trap = self.lift_library_trap();
+                    let infer = self.inferior.as_mut().unwrap();
+                    let dwarf_data = find_module(&self.dwarf_data, &self.libraries, rip);
+                    let result = infer.step_line(dwarf_data, &self.breakpoints_map, step_into);
+                    self.restore_library_trap(library_trap, &result);
+                    match result {
+                        Ok(status) => self.report_status(status, false),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                DebuggerCommand::Finish => {
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    let rip = match self.inferior.as_ref().unwrap().getregs() {
+                        Ok(regs) => regs.rip as usize,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    let dwarf_data = self.module(rip);
+                    let name = dwarf_data
+                        .get_function_from_addr(rip)
+                        .or_else(|| dwarf_data.get_symbol_from_addr(rip).map(|name| name.to_string()));
+                    match name {
+                        Some(func) => println!("Run till exit from {}", func),
+                        None => println!("Run till exit from {:#x}", rip),
+                    }
+                    let func = dwarf_data.get_function_containing_addr(rip);
+                    let has_debug_info = func.is_some();
+                    let return_type = func.and_then(|func| func.return_type.clone());
+                    let library_trap = self.lift_library_trap();
                     let infer = self.inferior.as_mut().unwrap();
-                    // check if inferior is stopped at a breakpoint
-                    if self.breakpoints_map.contains_key(&self.stopped_rip) {
-                        // println!("It's a breakpoint!");
-                        // go to next instruction
-                        match infer.step() {
-                            Ok(status) => {
-                                match status {
-                                    Status::Exited(exit_code) => {
-                                        println!("Child exited (status {})", exit_code);
-                                        continue;
-                                    }
-                                    Status::Stopped(_, rip) => {
-                                        // restore 0xcc in the breakpoint location
-                                        match infer.write_byte(self.stopped_rip, 0xcc) {
-                                            Ok(_) => {
-                                                // should be the orig_byte we have saved
-                                            }
-                                            Err(e) => {
-                                                panic!("Reinstall breakpoint failed: {}", e);
-                                            }
-                                        }
-                                        self.stopped_rip = rip;
-                                    },
-                                    Status::Signaled(_) => {
-                                        // nothing
-                                    },
+                    let thread = infer.current_thread();
+                    let dwarf_data = find_module(&self.dwarf_data, &self.libraries, rip);
+                    let result = infer.finish(dwarf_data, &self.breakpoints_map);
+                    self.restore_library_trap(library_trap, &result);
+                    match result {
+                        Ok(status) => {
+                            // Another thread may have stopped first
+                            let returned = match status {
+                                Status::Stopped(_, _) => {
+                                    self.inferior.as_ref().unwrap().current_thread() == thread
                                 }
-                            }
-                            Err(e) => {
-                                panic!("Exec step error {}", e);
+                                _ => false,
+                            };
+                            self.report_status(status, false);
+                            if returned && (return_type.is_some() || !has_debug_info) {
+                                self.print_return_value(return_type);
                             }
                         }
+                        Err(e) => println!("{}", e),
                     }
-                    self.continue_exec();
                 }
-                DebuggerCommand::Backtrace => {
-                    // check valid inferior
+                DebuggerCommand::Until(args) => {
                     if self.inferior.is_none() {
                         println!("The program is not being run.");
                         continue;
                     }
-                    let infer = self.inferior.as_mut().unwrap();
-                    match infer.print_backtrace(&self.dwarf_data) {
-                        Ok(()) => {}
-                        Err(e) => println!("{}", e),
+                    match args.as_slice() {
+                        [] => self.until_next_line(),
+                        [location] => self.advance(location),
+                        _ => println!("Usage: until [<location>]"),
+                    }
+                }
+                DebuggerCommand::Advance(args) => {
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    match args.as_slice() {
+                        [] => println!("Argument required (a location)."),
+                        [location] => self.advance(location),
+                        _ => println!("Usage: advance <location>"),
+                    }
+                }
+                DebuggerCommand::Print(args) => {
+                    if args.is_empty() {
+                        println!("Usage: print <expression>");
+                        continue;
+                    }
+                    if self.process().is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.print_expression(&args.join(" "));
+                }
+                DebuggerCommand::Set(args) => {
+                    if let Some(setting) = args.first().filter(|arg| {
+                        arg.as_str() == "follow-fork-mode" || arg.as_str() == "detach-on-fork"
+                    }) {
+                        self.set_fork_policy(setting, args.get(1).map(|arg| arg.as_str()));
+                        continue;
+                    }
+                    let args = match args.first().map(|s| s.as_str()) {
+                        Some("var") | Some("variable") => &args[1..],
+                        _ => &args[..],
+                    };
+                    if args.is_empty() {
+                        println!("Usage: set [var] <variable|$register> = <expression>");
+                        continue;
                     }
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.assign(&args.join(" "));
                 }
-                DebuggerCommand::Break(args) => {
-                    if args.len() != 1 {
-                        println!("invalid break targets");
+                DebuggerCommand::Backtrace => {
+                    // check valid inferior
+                    if self.process().is_none() {
+                        println!("No stack.");
                         continue;
                     }
-                    let token = &args[0];
-                    if token.starts_with("*") { // raw address mode
-                        let addr_str = &token[1..];
-                        // println!("{}", addr_str);
-                        let addr_without_0x = if addr_str.to_lowercase().starts_with("0x") {
-                            &addr_str[2..]
+                    self.print_backtrace();
+                }
+                DebuggerCommand::Break(args) => self.break_command(&args, false),
+                DebuggerCommand::Tbreak(args) => self.break_command(&args, true),
+                DebuggerCommand::Info(args) => match args.first().map(|s| s.as_str()) {
+                    Some("b") | Some("break") | Some("breakpoints") => self.print_breakpoints(),
+                    Some("r") | Some("registers") => self.print_registers(&args[1..], false),
+                    Some("all-registers") => self.print_registers(&args[1..], true),
+                    Some("shared") | Some("sharedlibrary") => self.print_libraries(),
+                    Some("locals") => self.print_frame_variables(false),
+                    Some("args") => self.print_frame_variables(true),
+                    Some("signals") | Some("handle") => self.print_signals(&args[1..]),
+                    Some("threads") => self.print_threads(),
+                    _ => println!(
+                        "Usage: info breakpoints|registers|all-registers|sharedlibrary|locals|args|signals|threads"
+                    ),
+                },
+                DebuggerCommand::Up(args) => {
+                    if let Some(count) = self.parse_frame_count(&args) {
+                        self.select_frame(Some(self.selected_frame + count), true);
+                    }
+                }
+                DebuggerCommand::Down(args) => {
+                    if let Some(count) = self.parse_frame_count(&args) {
+                        if self.selected_frame == 0 {
+                            println!("Bottom (innermost) frame selected; you cannot go down.");
                         } else {
-                            &addr_str
-                        };
-                        let decoded = usize::from_str_radix(addr_without_0x, 16);
-                        match decoded {
-                            Ok(addr) => {
-                                println!("Set breakpoint {} at {:#x}", self.breakpoints.len(), addr);
-                                self.breakpoints.push(addr);
-                                self.breakpoints_map.insert(addr, Breakpoint::new(addr, 0).unwrap());
-                            }
-                            Err(e) => {
-                                println!("Given address error: {}", e);
-                            }
+                            self.select_frame(Some(self.selected_frame.saturating_sub(count)), false);
                         }
-                        continue; // stop setting
-                    }
-                    // solve line number modes
-                    match token.parse::<usize>() {
-                        Ok(line_number) => {    // line number mode
-                            match self.dwarf_data.get_addr_for_line(None, line_number) {
-                                Some(addr) => {
-                                    println!("Set breakpoint {} at {:#x}", self.breakpoints.len(), addr);
-                                    self.breakpoints.push(addr);
-                                    self.breakpoints_map.insert(addr, Breakpoint::new(addr, 0).unwrap());
-                                }
-                                None => {
-                                    println!("No such line number {}", line_number);
-                                }
+                    }
+                }
+                DebuggerCommand::Frame(args) => {
+                    if let Some(number) = self.parse_frame_count(&args) {
+                        self.select_frame(args.first().map(|_| number), false);
+                    }
+                }
+                DebuggerCommand::Delete(args) => {
+                    for number in self.parse_breakpoint_numbers(&args) {
+                        if self.breakpoints.iter().any(|bp| bp.number == number) {
+                            self.delete_breakpoint(number);
+                        } else if let Some(index) =
+                            self.watchpoints.iter().position(|wp| wp.number == number)
+                        {
+                            self.delete_watchpoint(index);
+                        } else if let Some(index) =
+                            self.catchpoints.iter().position(|cp| cp.number == number)
+                        {
+                            self.catchpoints.remove(index);
+                            self.sync_catchpoints();
+                        } else {
+                            println!("No breakpoint number {}.", number);
+                        }
+                    }
+                }
+                DebuggerCommand::Disable(args) => self.set_breakpoints_enabled(&args, false),
+                DebuggerCommand::Enable(args) => self.set_breakpoints_enabled(&args, true),
+                DebuggerCommand::Watch(args) => self.add_watchpoint(WatchKind::Write, &args.join(" ")),
+                DebuggerCommand::Rwatch(args) => self.add_watchpoint(WatchKind::Read, &args.join(" ")),
+                DebuggerCommand::Awatch(args) => self.add_watchpoint(WatchKind::Access, &args.join(" ")),
+                DebuggerCommand::Trace(args) => self.trace_command(&args),
+                DebuggerCommand::Catch(args) => self.add_catchpoint(&args),
+                DebuggerCommand::Attach(args) => {
+                    match args.first().and_then(|arg| arg.parse::<i32>().ok()) {
+                        Some(pid) if args.len() == 1 => self.attach(pid),
+                        _ => println!("Usage: attach <pid>"),
+                    }
+                }
+                DebuggerCommand::Detach => {
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.detach();
+                }
+                DebuggerCommand::Handle(args) => self.handle_signals(&args),
+                DebuggerCommand::Thread(args) => self.select_thread(&args),
+                DebuggerCommand::Signal(args) => {
+                    if self.inferior.is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    let sig = match args.as_slice() {
+                        [arg] if arg == "0" => None,
+                        [arg] => match parse_signal(arg) {
+                            Some(sig) => Some(sig),
+                            None => {
+                                println!("Bad signal \"{}\". Use \"info signals\" for a list of signals.", arg);
+                                continue;
                             }
-                            continue; // stop setting
+                        },
+                        _ => {
+                            println!("Argument required (signal number).");
+                            continue;
                         }
-                        Err(_) => {}
-                    }
-                    // solve name mode
-                    match self.dwarf_data.get_addr_for_function(None, token) {
-                        Some(addr) => {
-                            println!("Set breakpoint {} at {:#x}", self.breakpoints.len(), addr);
-                            self.breakpoints.push(addr);
-                            self.breakpoints_map.insert(addr, Breakpoint::new(addr, 0).unwrap());
-                            continue; // stop setting
+                    };
+                    match sig {
+                        Some(sig) => println!("Continuing with signal {}.", sig),
+                        None => println!("Continuing with no signal."),
+                    }
+                    self.inferior.as_mut().unwrap().set_pending_signal(sig);
+                    self.continue_exec();
+                }
+                DebuggerCommand::Disassemble(args) => {
+                    if self.process().is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.disassemble(&args.join(" "));
+                }
+                DebuggerCommand::Examine(format, args) => {
+                    if args.is_empty() && self.next_examine.is_none() {
+                        println!("Argument required (starting display address).");
+                        continue;
+                    }
+                    if self.process().is_none() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.examine_memory(&format, &args.join(" "));
+                }
+                DebuggerCommand::Ignore(args) => {
+                    let parsed = match args.as_slice() {
+                        [number, count] => match (number.parse::<usize>(), count.parse::<usize>()) {
+                            (Ok(number), Ok(count)) => Some((number, count)),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    let (number, count) = match parsed {
+                        Some(parsed) => parsed,
+                        None => {
+                            println!("Usage: ignore <breakpoint number> <count>");
+                            continue;
+                        }
+                    };
+                    match self.breakpoints.iter_mut().find(|bp| bp.number == number) {
+                        Some(bp) => {
+                            bp.ignore_count = count;
+                            match count {
+                                0 => println!("Will stop next time breakpoint {} is reached.", number),
+                                1 => println!("Will ignore next crossing of breakpoint {}.", number),
+                                _ => println!(
+                                    "Will ignore next {} crossings of breakpoint {}.",
+                                    count, number
+                                ),
+                            }
                         }
-                        None => {}
+                        None => println!("No breakpoint number {}.", number),
                     }
-                    // unvalid token
-                    println!("Unvalid breakpoint!");
                 }
             }
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
-                }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+    /// Loads a core dump of the target program for post-mortem inspection
+    pub fn load_core(&mut self, path: &str) {
+        match CoreFile::load(path, &self.target) {
+            Ok(core) => {
+                println!("Core was generated by process {}.", core.pid);
+                match Signal::try_from(core.signal) {
+                    Ok(signal) => println!("Program terminated with signal {}.", signal),
+                    Err(_) => println!("Program terminated with signal {}.", core.signal),
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                let entry = core.entry;
+                let rip = core.getregs().map(|regs| regs.rip as usize);
+                self.core = Some(core);
+                self.selected_frame = 0;
+                self.new_address_space(entry);
+                self.load_libraries();
+                if let Ok(rip) = rip {
+                    self.print_location(rip);
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
-                    }
+            }
+            Err(message) => println!("{}", message),
+        }
+    }
+
+    /// The program whose state print, backtrace, x and info registers look at: the live
+    /// inferior if there is one, the core dump otherwise
+    fn process(&self) -> Option<&dyn ProcessState> {
+        match &self.inferior {
+            Some(infer) => Some(infer),
+            None => self.core.as_ref().map(|core| core as &dyn ProcessState),
+        }
+    }
+
+    /// Starts debugging a process that is already running. Debugging information is reloaded
+    /// if the process runs a different executable than the current target.
+    pub fn attach(&mut self, pid: i32) {
+        if self.inferior.is_some() {
+            println!("A program is being debugged already. Detach from it first.");
+            return;
+        }
+        if !self.load_target(process_executable(pid)) {
+            return;
+        }
+        if let Some(infer) = Inferior::attach(Pid::from_raw(pid)) {
+            println!("Attaching to process {}", pid);
+            self.take_over(infer);
+        }
+    }
+
+    /// Starts debugging a process that is stopped somewhere in the target program, installing
+    /// our traps in it
+    fn take_over(&mut self, mut infer: Inferior) {
+        let pid = infer.pid().as_raw();
+        infer.set_signal_policies(&self.signal_policies);
+        infer.set_fork_policy(self.fork_policy);
+        infer.set_catch_policy(self.catch_policy());
+        let rip = infer.getregs().map(|regs| regs.rip as usize);
+        self.inferior = Some(infer);
+        self.core = None;
+        self.selected_frame = 0;
+        self.new_address_space(process_entry(pid));
+        self.load_libraries();
+        self.inferior.as_mut().unwrap().install_breakpoints(&mut self.breakpoints_map);
+        self.install_watchpoints();
+        if let Ok(rip) = rip {
+            self.print_location(rip);
+        }
+    }
+
+    /// Makes `target` the program being debugged, unless it already is, and loads its debug
+    /// information. Breakpoints are set again at their locations in the new program, and
+    /// watchpoints, which refer to the old program's data, are deleted. Returns false if the
+    /// debug information cannot be read.
+    fn load_target(&mut self, target: String) -> bool {
+        let same_file = match (std::fs::canonicalize(&target), std::fs::canonicalize(&self.target)) {
+            (Ok(new), Ok(old)) => new == old,
+            _ => target == self.target,
+        };
+        if same_file {
+            return true;
+        }
+        match load_dwarf_data(&target) {
+            Ok(dwarf_data) => {
+                println!("Reading symbols from {}", target);
+                self.dwarf_data = dwarf_data;
+                self.target = target;
+            }
+            Err(message) => {
+                println!("{}", message);
+                return false;
+            }
+        }
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        for bp in breakpoints.iter_mut() {
+            match self.parse_location(&bp.location) {
+                Ok(addr) => bp.addr = addr,
+                Err(_) => {
+                    println!("Deleting breakpoint {}: no location {} in {}.", bp.number, bp.location, self.target);
+                    bp.number = 0;
                 }
             }
         }
+        breakpoints.retain(|bp| bp.number != 0);
+        self.breakpoints = breakpoints;
+        for wp in self.watchpoints.drain(..) {
+            println!("Watchpoint {} deleted because the program has changed.", wp.number);
+        }
+        true
     }
 
-    /// continue
-    /// using ptrace::cont
-    /// if inferior stopped at a breakpoint (i.e. (%rip - 1) matches a breakpoint address):
-    ///     restore the first byte of the instruction we replaced
-    ///     set %rip = %rip - 1 to rewind the instruction pointer
-    fn continue_exec(&mut self) {
+    /// Carries on with the program the inferior has just executed. Its traps are installed as
+    /// for `run`, after loading its debug information if it is not the target.
+    fn follow_exec(&mut self) {
+        let pid = self.inferior.as_ref().unwrap().pid().as_raw();
+        let target = process_executable(pid);
+        println!("process {} is executing new program: {}", pid, target);
+        // The traps went away with the old program
+        self.one_shots.clear();
+        self.load_target(target);
+        self.selected_frame = 0;
+        self.start_program(process_entry(pid));
+    }
+
+    /// Takes over the next process held after a fork, if any, once the inferior is gone
+    fn take_held_process(&mut self) {
+        let infer = match self.inferior.take().and_then(|mut infer| infer.take_held()) {
+            Some(infer) => infer,
+            None => return,
+        };
+        println!("[Switching to process {}]", infer.pid());
+        if self.load_target(process_executable(infer.pid().as_raw())) {
+            self.take_over(infer);
+        } else {
+            self.inferior = Some(infer);
+            self.detach();
+        }
+    }
+
+    /// Implements `set follow-fork-mode parent|child` and `set detach-on-fork on|off`
+    fn set_fork_policy(&mut self, setting: &str, value: Option<&str>) {
+        match (setting, value) {
+            ("follow-fork-mode", Some("parent")) => self.fork_policy.follow_child = false,
+            ("follow-fork-mode", Some("child")) => self.fork_policy.follow_child = true,
+            ("follow-fork-mode", Some(value)) => {
+                println!("Undefined item: \"{}\".", value);
+                return;
+            }
+            ("follow-fork-mode", None) => {
+                println!("Requires an argument. Valid arguments are child, parent.");
+                return;
+            }
+            (_, Some("on")) => self.fork_policy.detach = true,
+            (_, Some("off")) => self.fork_policy.detach = false,
+            _ => {
+                println!("\"on\" or \"off\" expected.");
+                return;
+            }
+        }
+        if let Some(infer) = self.inferior.as_mut() {
+            infer.set_fork_policy(self.fork_policy);
+        }
+    }
+
+    /// Removes our breakpoints from the inferior and lets it run on untraced
+    fn detach(&mut self) {
         let infer = self.inferior.as_mut().unwrap();
-        let status = infer.cont_exec().unwrap();
-        match status {
-            Status::Exited(exit_code) => println!("Child exited (status {})", exit_code),
-            Status::Stopped(signal, rip) => {
-                println!("Child stopped (signal {})", signal);
-                // println!("rip: {:#x}", rip);
-                self.stopped_rip = rip;
-                let break_addr = rip - 1;
-                if self.breakpoints_map.contains_key(&break_addr) {
-                    // println!("It's a breakpoint!");
-                    // restore the first byte of the instruction we replaced
-                    let break_point = self.breakpoints_map.get(&break_addr).unwrap();
-                    match infer.write_byte(break_addr, break_point.orig_byte) {
-                        Ok(_) => {
-                            // note that the returned orig_byte should be 0xcc
-                        }
-                        Err(e) => {
-                            println!("Fail to continue from breakpoint at {:#x}: {}", break_addr, e);
-                        }
-                    }
-                    // set %rip = %rip - 1 to rewind the instruction pointer
-                    match infer.set_rip(rip-1) {
-                        Ok(_) => {}
-                        Err(e) => panic!("{}", e),
-                    }
-                    self.stopped_rip = rip-1;
-                }
-                match self.dwarf_data.get_line_from_addr(rip) {
-                    Some(line) => {
-                        println!("Stopped at {}:{}", line.file, line.number);
-                    },
-                    None => {}
-                }              
-            },
-            Status::Signaled(_) => {
-                // nothing
-            },
+        match infer.detach(&self.breakpoints_map) {
+            Ok(()) => {
+                println!("Detaching from program: {}, process {}", self.target, infer.pid());
+                self.inferior = None;
+            }
+            Err(e) => println!("Could not detach: {}", e),
+        }
+    }
+
+    /// Starts the target program with the given arguments, killing the inferior we had
+    /// before. Returns false if it could not be started.
+    fn start_inferior(&mut self, args: &[String]) -> bool {
+        let mut inferior = match Inferior::new(&self.target, &args.to_vec()) {
+            Some(inferior) => inferior,
+            None => {
+                println!("Error starting subprocess");
+                return false;
+            }
         };
+        inferior.set_signal_policies(&self.signal_policies);
+        inferior.set_fork_policy(self.fork_policy);
+        inferior.set_catch_policy(self.catch_policy());
+        // Check existed inferior and kill it
+        if let Some(infer) = self.inferior.as_mut() {
+            infer.kill();
+        }
+        // Create the inferior
+        let entry = process_entry(inferior.pid().as_raw());
+        self.inferior = Some(inferior);
+        self.core = None;
+        self.syscall_entries.clear();
+        self.start_program(entry);
+        true
+    }
+
+    /// Runs the target program to completion without a prompt, logging its system calls like
+    /// strace does. Signals are passed on to it without stopping it, SIGINT included, so that
+    /// ctrl+c ends the program.
+    pub fn trace(&mut self, args: &[String]) {
+        self.trace_syscalls = true;
+        for (sig, policy) in self.signal_policies.iter_mut() {
+            policy.stop = false;
+            policy.pass = policy.pass || *sig == Signal::SIGINT;
+        }
+        if self.start_inferior(args) {
+            self.continue_exec();
+        }
+        // Something the program did stopped it nevertheless
+        if let Some(mut infer) = self.inferior.take() {
+            infer.kill();
+        }
+    }
+
+    /// Gets a process that has just started a program ready to run. The breakpoints are only
+    /// installed at the entry point, when the shared libraries are mapped.
+    fn start_program(&mut self, entry: Option<usize>) {
+        self.new_address_space(entry);
+        match entry {
+            Some(entry) => {
+                self.entry_trap = Some(entry);
+                self.update_trap(entry);
+            }
+            None => self.inferior.as_mut().unwrap().install_breakpoints(&mut self.breakpoints_map),
+        }
+        self.install_watchpoints();
+    }
+
+    /// Gets ready for a new process, which has none of our traps installed yet. The executable
+    /// is relocated to where it was loaded, given the entry point the process started at, and
+    /// the shared libraries of the previous process are forgotten.
+    fn new_address_space(&mut self, entry: Option<usize>) {
+        if let Some(entry) = entry {
+            self.relocate_executable(entry);
+        }
+        self.libraries.clear();
+        self.entry_trap = None;
+        self.library_trap = None;
+        self.leave_watch_scopes();
+        self.breakpoints_map = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.enabled)
+            .map(|bp| (bp.addr, Breakpoint::new(bp.addr, 0).unwrap()))
+            .collect();
+    }
+
+    /// Moves the executable's debug information, and the breakpoints and watchpoints in it, by
+    /// the difference between the actual and the expected entry point. This is the load bias of
+    /// a position-independent executable.
+    fn relocate_executable(&mut self, entry: usize) {
+        let delta = entry.wrapping_sub(self.dwarf_data.entry());
+        if delta == 0 {
+            return;
+        }
+        let (start, end) = self.dwarf_data.address_range();
+        let in_executable = |addr: usize| start <= addr && addr < end;
+        for bp in self.breakpoints.iter_mut().filter(|bp| in_executable(bp.addr)) {
+            bp.addr = bp.addr.wrapping_add(delta);
+        }
+        for wp in self.watchpoints.iter_mut().filter(|wp| in_executable(wp.addr)) {
+            wp.addr = wp.addr.wrapping_add(delta);
+        }
+        let load_bias = self.dwarf_data.load_bias().wrapping_add(delta);
+        self.dwarf_data.relocate(load_bias);
     }
+
+    /// Rereads the dynamic loader's list of shared libraries, loading the debug information of
+    /// new ones and dropping the ones that were unloaded. A live inferior also gets a trap at
+    /// the loader's r_brk hook, so that we hear about dlopen and dlclose.
+    fn load_libraries(&mut self) {
+        let dynamic_section = match (self.process(), self.dwarf_data.dynamic_section()) {
+            (Some(_), Some(addr)) => addr,
+            _ => return,
+        };
+        let link_map = match read_link_map(self.process().unwrap(), dynamic_section, &self.breakpoints_map) {
+            Some(link_map) => link_map,
+            None => return,
+        };
+        if self.inferior.is_some() && self.library_trap != Some(link_map.r_brk) {
+            let old_trap = self.library_trap.replace(link_map.r_brk);
+            if let Some(old_trap) = old_trap {
+                self.update_trap(old_trap);
+            }
+            self.update_trap(link_map.r_brk);
+        }
+        if !link_map.consistent {
+            return;
+        }
+        let mut libraries = Vec::new();
+        let mut loaded_new = false;
+        for (path, load_bias) in link_map.libraries {
+            let loaded = self.libraries.iter().position(|library| {
+                library.path == path && library.dwarf_data.load_bias() == load_bias
+            });
+            match loaded {
+                Some(index) => libraries.push(self.libraries.remove(index)),
+                None => match DwarfData::from_file(&path) {
+                    Ok(mut dwarf_data) => {
+                        dwarf_data.relocate(load_bias);
+                        libraries.push(SharedLibrary { path, dwarf_data });
+                        loaded_new = true;
+                    }
+                    Err(e) => println!("Could not read symbols from {}: {:?}", path, e),
+                },
+            }
+        }
+        self.libraries = libraries;
+        // Pending breakpoints may belong to the new libraries
+        if loaded_new && self.entry_trap.is_none() {
+            if let Some(infer) = self.inferior.as_mut() {
+                infer.install_breakpoints(&mut self.breakpoints_map);
+            }
+        }
+    }
+
+    /// Removes the trap at the loader's r_brk hook for the duration of step, next or finish, so
+    /// that a dlopen in the code being stepped over does not stop them. Returns the trap for
+    /// restore_library_trap.
+    fn lift_library_trap(&mut self) -> Option<usize> {
+        let trap = self.library_trap.take();
+        if let Some(trap) = trap {
+            self.update_trap(trap);
+        }
+        trap
+    }
+
+    /// Puts back the trap removed by lift_library_trap and catches up on the libraries that
+    /// were loaded or unloaded in the meantime. Nothing is done if the inferior exited, or
+    /// executed another program, which has a loader of its own.
+    fn restore_library_trap(&mut self, trap: Option<usize>, result: &Result<Status, nix::Error>) {
+        match result {
+            Ok(Status::Stopped(_, _)) | Ok(Status::Caught(_, _)) | Err(_) => {}
+            _ => return,
+        }
+        if self.inferior.is_none() {
+            return;
+        }
+        if let Some(trap) = trap {
+            self.library_trap = Some(trap);
+            self.update_trap(trap);
+        }
+        self.load_libraries();
+    }
+
+    /// Returns the debug information of the executable followed by that of the libraries
+    fn modules(&self) -> Vec<&DwarfData> {
+        std::iter::once(&self.dwarf_data)
+            .chain(self.libraries.iter().map(|library| &library.dwarf_data))
+            .collect()
+    }
+
+    /// Returns the debug information of the object mapped at `addr`
+    fn module(&self, addr: usize) -> &DwarfData {
+        find_module(&self.dwarf_data, &self.libraries, addr)
+    }
+
+    /// Resolves a breakpoint location: `*addr`, `[file:]line`, `[file:]function`, or
+    /// `library:function` for a function in a loaded shared library. A bare line number refers
+    /// to the file the program is stopped in, or else the one defining main.
+    fn parse_location(&self, token: &str) -> Result<usize, String> {
+        if token.starts_with('*') { // raw address mode
+            let (_, addr_str) = token.split_at(1);
+            let addr_without_0x = if addr_str.to_lowercase().starts_with("0x") {
+                &addr_str[2..]
+            } else {
+                addr_str
+            };
+            return usize::from_str_radix(addr_without_0x, 16)
+                .map_err(|e| format!("Given address error: {}", e));
+        }
+        if let Some(colon) = token.rfind(':') {
+            let (name, spec) = (&token[..colon], &token[colon + 1..]);
+            if let Some(library) = self.libraries.iter().find(|library| library.matches(name)) {
+                return function_address(&library.dwarf_data, spec)
+                    .ok_or_else(|| format!("Function \"{}\" not defined in \"{}\".", spec, name));
+            }
+            let files: Vec<(&DwarfData, &File)> = self
+                .modules()
+                .into_iter()
+                .flat_map(|dwarf_data| {
+                    dwarf_data.find_files(name).into_iter().map(move |file| (dwarf_data, file))
+                })
+                .collect();
+            return match files.as_slice() {
+                [] if name.contains(".so") => {
+                    Err(format!("No shared library matching \"{}\" is loaded.", name))
+                }
+                [] => Err(format!("No source file named {}.", name)),
+                [(dwarf_data, file)] => match spec.parse::<usize>() {
+                    Ok(line_number) => dwarf_data
+                        .get_addr_for_line(Some(&file.name), line_number)
+                        .ok_or_else(|| format!("Line {} is out of range for \"{}\".", line_number, file.name)),
+                    Err(_) => dwarf_data
+                        .get_addr_for_function(Some(&file.name), spec)
+                        .ok_or_else(|| format!("Function \"{}\" not defined in \"{}\".", spec, file.name)),
+                },
+                _ => {
+                    let names: Vec<&str> = files.iter().map(|(_, file)| file.name.as_str()).collect();
+                    Err(format!("Source file name \"{}\" is ambiguous: {}.", name, names.join(", ")))
+                }
+            };
+        }
+        // solve line number modes
+        if let Ok(line_number) = token.parse::<usize>() {
+            let (dwarf_data, file) = match self.current_file() {
+                Some((dwarf_data, file)) => (dwarf_data, Some(file.name.as_str())),
+                None => (&self.dwarf_data, None),
+            };
+            return dwarf_data
+                .get_addr_for_line(file, line_number)
+                .ok_or_else(|| format!("No line {} in the current file.", line_number));
+        }
+        // solve name mode
+        let funcs: Vec<(&DwarfData, &File, &Function)> = self
+            .modules()
+            .into_iter()
+            .flat_map(|dwarf_data| {
+                dwarf_data
+                    .find_functions(None, token)
+                    .into_iter()
+                    .map(move |(file, func)| (dwarf_data, file, func))
+            })
+            .collect();
+        match funcs.as_slice() {
+            [] => self
+                .modules()
+                .into_iter()
+                .find_map(|dwarf_data| dwarf_data.get_symbol(token).map(|(address, _)| address))
+                .ok_or_else(|| format!("Function \"{}\" not defined.", token)),
+            [(dwarf_data, _, func)] => Ok(dwarf_data.get_addr_after_prologue(func)),
+            _ => {
+                let places: Vec<String> = funcs
+                    .iter()
+                    .map(|(_, file, func)| format!("{}:{}", file.name, func.line_number))
+                    .collect();
+                Err(format!(
+                    "Function \"{}\" is ambiguous: defined at {}. Use file:function to pick one.",
+                    token,
+                    places.join(", ")
+                ))
+            }
+        }
+    }
+
+    /// Returns the compilation unit the selected frame is in, with the object it belongs to
+    fn current_file(&self) -> Option<(&DwarfData, &File)> {
+        self.process()?;
+        let pc = self.selected_frame().ok()?.pc();
+        let dwarf_data = self.module(pc);
+        Some((dwarf_data, dwarf_data.get_file_containing_addr(pc)?))
+    }
+
+    /// Implements `break <location> [if <condition>]`, and tbreak when `temporary` is set
+    fn break_command(&mut self, args: &[String], temporary: bool) {
+        let condition = if args.len() > 2 && args[1] == "if" {
+            Some(args[2..].join(" "))
+        } else if args.len() == 1 {
+            None
+        } else {
+            let name = if temporary { "tbreak" } else { "break" };
+            println!("Usage: {} <location> [if <condition>]", name);
+            return;
+        };
+        // The condition is only checked for syntax here, before its scope is known
+        let is_type = |name: &str| self.modules().iter().any(|module| module.find_type(name).is_some());
+        if let Some(Err(message)) = condition.as_ref().map(|text| expr::parse(text, &is_type)) {
+            println!("{}", message);
+            return;
+        }
+        match self.parse_location(&args[0]) {
+            Ok(addr) => self.add_breakpoint(addr, &args[0], condition, temporary),
+            Err(message) => println!("{}", message),
+        }
+    }
+
+    /// Creates a new numbered breakpoint at `addr`, optionally guarded by a condition
+    fn add_breakpoint(&mut self, addr: usize, location: &str, condition: Option<String>, temporary: bool) {
+        self.next_breakpoint_number += 1;
+        let number = self.next_breakpoint_number;
+        if temporary {
+            println!("Temporary breakpoint {} at {:#x}", number, addr);
+        } else {
+            println!("Set breakpoint {} at {:#x}", number, addr);
+        }
+        self.breakpoints.push(UserBreakpoint {
+            number,
+            addr,
+            location: location.to_string(),
+            enabled: true,
+            hit_count: 0,
+            condition,
+            ignore_count: 0,
+            temporary,
+        });
+        self.update_trap(addr);
+    }
+
+    /// Removes breakpoint `number`, and its 0xcc unless another breakpoint is at the same place
+    fn delete_breakpoint(&mut self, number: usize) {
+        if let Some(index) = self.breakpoints.iter().position(|bp| bp.number == number) {
+            let bp = self.breakpoints.remove(index);
+            self.update_trap(bp.addr);
+        }
+    }
+
+    /// Parses the arguments of delete/disable/enable. No arguments means every breakpoint.
+    fn parse_breakpoint_numbers(&self, args: &[String]) -> Vec<usize> {
+        if args.is_empty() {
+            return self
+                .breakpoints
+                .iter()
+                .map(|bp| bp.number)
+                .chain(self.watchpoints.iter().map(|wp| wp.number))
+                .chain(self.catchpoints.iter().map(|cp| cp.number))
+                .collect();
+        }
+        args.iter()
+            .filter_map(|arg| match arg.parse::<usize>() {
+                Ok(number) => Some(number),
+                Err(_) => {
+                    println!("Invalid breakpoint number {}", arg);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn set_breakpoints_enabled(&mut self, args: &[String], enabled: bool) {
+        for number in self.parse_breakpoint_numbers(args) {
+            if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.number == number) {
+                bp.enabled = enabled;
+                let addr = bp.addr;
+                self.update_trap(addr);
+            } else if let Some(index) = self.watchpoints.iter().position(|wp| wp.number == number) {
+                let wp = &self.watchpoints[index];
+                let needed = watch_chunks(wp.addr, wp.entity_type.size).len();
+                if enabled && !wp.enabled && self.watch_slots.len() + needed > WATCH_SLOTS {
+                    println!("Not enough hardware debug registers to enable watchpoint {}.", number);
+                    continue;
+                }
+                self.watchpoints[index].enabled = enabled;
+                self.sync_watchpoints();
+            } else if let Some(cp) = self.catchpoints.iter_mut().find(|cp| cp.number == number) {
+                cp.enabled = enabled;
+                self.sync_catchpoints();
+            } else {
+                println!("No breakpoint number {}.", number);
+            }
+        }
+    }
+
+    /// Implements watch, rwatch and awatch
+    fn add_watchpoint(&mut self, kind: WatchKind, text: &str) {
+        if text.is_empty() {
+            println!("Usage: watch <variable|*address>");
+            return;
+        }
+        let (addr, entity_type) = match self.resolve_watch_target(text) {
+            Ok(target) => target,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        if self.watch_slots.len() + watch_chunks(addr, entity_type.size).len() > WATCH_SLOTS {
+            println!("Not enough hardware debug registers to watch {}.", text);
+            return;
+        }
+        self.next_breakpoint_number += 1;
+        let number = self.next_breakpoint_number;
+        println!("{} {}: {}", kind.describe(), number, text);
+        let old_value = self.inferior.as_ref().and_then(|infer| {
+            infer.read_memory(addr, entity_type.size, &self.breakpoints_map).ok()
+        });
+        let scope = self.watch_scope(addr);
+        let return_addr = scope.as_ref().map(|scope| scope.return_addr);
+        self.watchpoints.push(Watchpoint {
+            number,
+            kind,
+            expr: text.to_string(),
+            addr,
+            entity_type,
+            old_value,
+            enabled: true,
+            hit_count: 0,
+            scope,
+        });
+        self.sync_watchpoints();
+        if let Some(return_addr) = return_addr {
+            self.update_trap(return_addr);
+        }
+    }
+
+    /// Finds the frame of the selected thread that `addr` lies in, between the frame's %rsp and
+    /// its CFA, if the object there is a local of the selected frame
+    fn watch_scope(&self, addr: usize) -> Option<WatchScope> {
+        let infer = self.inferior.as_ref()?;
+        let frame = self.selected_frame().ok()?;
+        let frame_end = infer.frame_base(&frame, self.module(frame.pc()), &self.breakpoints_map).ok()?;
+        if addr < frame.regs.rsp as usize || addr >= frame_end {
+            return None;
+        }
+        Some(WatchScope {
+            thread: infer.current_thread().1,
+            frame_end,
+            return_addr: infer.read_word(frame_end - 8).ok()?,
+        })
+    }
+
+    /// Removes the watchpoint at `index` in `watchpoints`, along with the trap watching its
+    /// frame return
+    fn delete_watchpoint(&mut self, index: usize) {
+        let wp = self.watchpoints.remove(index);
+        self.sync_watchpoints();
+        if let Some(scope) = wp.scope {
+            self.update_trap(scope.return_addr);
+        }
+    }
+
+    /// Deletes the watchpoints on locals, whose frames are gone with the process
+    fn leave_watch_scopes(&mut self) {
+        while let Some(index) = self.watchpoints.iter().position(|wp| wp.scope.is_some()) {
+            println!("{}", left_scope(self.watchpoints[index].number));
+            self.delete_watchpoint(index);
+        }
+    }
+
+    /// Implements `catch syscall [name|number]...`, `catch signal [signal...|all]`, `catch fork`,
+    /// `catch vfork` and `catch exec`
+    fn add_catchpoint(&mut self, args: &[String]) {
+        let kind = match args.first().map(|arg| arg.as_str()) {
+            Some("syscall") => {
+                let mut numbers = Vec::new();
+                for arg in &args[1..] {
+                    match arg.parse::<u64>().ok().or_else(|| syscalls::syscall_number(arg)) {
+                        Some(number) => numbers.push(number),
+                        None => {
+                            println!("Unknown syscall name '{}'.", arg);
+                            return;
+                        }
+                    }
+                }
+                CatchKind::Syscall(numbers)
+            }
+            Some("signal") if args.len() == 2 && args[1] == "all" => CatchKind::AnySignal,
+            Some("signal") => {
+                let mut signals = Vec::new();
+                for arg in &args[1..] {
+                    match parse_signal(arg) {
+                        Some(Signal::SIGTRAP) => {
+                            println!("SIGTRAP is used by the debugger.");
+                            return;
+                        }
+                        Some(sig) => signals.push(sig),
+                        None => {
+                            println!("Only signals 1-15 are valid as numeric signals.");
+                            return;
+                        }
+                    }
+                }
+                CatchKind::Signal(signals)
+            }
+            Some("fork") if args.len() == 1 => CatchKind::Fork,
+            Some("vfork") if args.len() == 1 => CatchKind::Vfork,
+            Some("exec") if args.len() == 1 => CatchKind::Exec,
+            _ => {
+                println!("Usage: catch syscall [name|number]... | signal [signal...|all] | fork | vfork | exec");
+                return;
+            }
+        };
+        self.next_breakpoint_number += 1;
+        let number = self.next_breakpoint_number;
+        println!("Catchpoint {} ({})", number, kind.describe());
+        self.catchpoints.push(Catchpoint {
+            number,
+            kind,
+            enabled: true,
+            hit_count: 0,
+        });
+        self.sync_catchpoints();
+    }
+
+    /// Works out which system calls, signals and forks the enabled catchpoints stop at
+    fn catch_policy(&self) -> CatchPolicy {
+        let mut policy = CatchPolicy::default();
+        for cp in self.catchpoints.iter().filter(|cp| cp.enabled) {
+            match &cp.kind {
+                // An empty list stands for every system call
+                CatchKind::Syscall(numbers) => {
+                    policy.syscalls = match policy.syscalls.take() {
+                        Some(caught) if caught.is_empty() || numbers.is_empty() => Some(Vec::new()),
+                        Some(mut caught) => {
+                            caught.extend(numbers);
+                            Some(caught)
+                        }
+                        None => Some(numbers.clone()),
+                    }
+                }
+                CatchKind::Fork => policy.fork = true,
+                CatchKind::Vfork => policy.vfork = true,
+                kind => policy.signals.extend(kind.signals()),
+            }
+        }
+        policy.trace_syscalls = self.trace_syscalls;
+        policy
+    }
+
+    /// Tells the inferior which events the catchpoints stop at
+    fn sync_catchpoints(&mut self) {
+        let policy = self.catch_policy();
+        if let Some(infer) = self.inferior.as_mut() {
+            infer.set_catch_policy(policy);
+        }
+    }
+
+    /// Returns the announcements of the enabled catchpoints the inferior stopping with `status`
+    /// triggers, and counts their hits. A system call is followed by its decoded arguments, and
+    /// on exit, by its return value.
+    fn check_catchpoints(&mut self, status: &Status) -> Vec<String> {
+        let syscall = |number: u64| match syscalls::syscall_name(number) {
+            Some(name) => name.to_string(),
+            None => number.to_string(),
+        };
+        let what = match status {
+            Status::Caught(CatchEvent::SyscallEntry(number), _) => format!("call to syscall {}", syscall(*number)),
+            Status::Caught(CatchEvent::SyscallExit(number), _) => format!("returned from syscall {}", syscall(*number)),
+            Status::Caught(CatchEvent::Fork(child), _) => format!("forked process {}", child),
+            Status::Caught(CatchEvent::Vfork(child), _) => format!("vforked process {}", child),
+            Status::Stopped(sig, _) => format!("signal {}", sig),
+            Status::Execed => format!("exec'd {}", self.target),
+            _ => return Vec::new(),
+        };
+        let mut messages = Vec::new();
+        for cp in self.catchpoints.iter_mut().filter(|cp| cp.enabled && cp.kind.matches(status)) {
+            cp.hit_count += 1;
+            messages.push(format!("Catchpoint {} ({})", cp.number, what));
+        }
+        let (number, exited) = match status {
+            _ if messages.is_empty() => return messages,
+            Status::Caught(CatchEvent::SyscallEntry(number), _) => (*number, false),
+            Status::Caught(CatchEvent::SyscallExit(number), _) => (*number, true),
+            _ => return messages,
+        };
+        if let Ok((call, regs)) = self.format_syscall(number, true) {
+            if exited {
+                messages.push(format!("{} = {}", call, syscalls::format_return(number, regs.rax)));
+            } else {
+                messages.push(call);
+            }
+        }
+        messages
+    }
+
+    /// Decodes the system call the current thread is stopped at, reading the strings it is
+    /// passed from memory. Arguments are named after the kernel's parameters if `named` is set.
+    /// The registers are returned as well, for the return value.
+    fn format_syscall(&self, number: u64, named: bool) -> Result<(String, libc::user_regs_struct), nix::Error> {
+        let infer = self.inferior.as_ref().unwrap();
+        let regs = infer.getregs()?;
+        let mut args = [0; 6];
+        for (arg, name) in args.iter_mut().zip(ARGUMENT_REGISTERS.iter()) {
+            *arg = register_value(&regs, name).unwrap();
+        }
+        let read_memory = |addr, len| infer.read_memory(addr, len, &self.breakpoints_map).ok();
+        Ok((syscalls::format_call(number, &args, named, &read_memory), regs))
+    }
+
+    /// Finds the source line the current thread made a system call from. That is the innermost
+    /// frame with line information, as the system call itself is usually made by the C library.
+    fn syscall_line(&self) -> Option<Line> {
+        let frames = self.inferior.as_ref().unwrap().unwind_stack(&self.modules()).ok()?;
+        frames
+            .iter()
+            .filter_map(|frame| self.module(frame.pc()).get_line_from_addr(frame.pc()))
+            .next()
+    }
+
+    /// Formats a system call for the syscall trace, e.g. `loop.c:12: write(1, "45\n", 3)`,
+    /// marked with the thread that made it unless that is the main one
+    fn describe_syscall(&self, number: u64) -> Result<String, nix::Error> {
+        let (call, _) = self.format_syscall(number, false)?;
+        let infer = self.inferior.as_ref().unwrap();
+        let tid = infer.current_thread().1;
+        let thread = if tid == infer.pid() { String::new() } else { format!("[LWP {}] ", tid) };
+        let line = match self.syscall_line() {
+            Some(line) => format!("{}: ", line),
+            None => String::new(),
+        };
+        Ok(format!("{}{}{}", thread, line, call))
+    }
+
+    /// Logs a system call the inferior stopped at if syscall tracing is on, and tells whether it
+    /// did. A call is logged once it returns, with the arguments it was made with and the source
+    /// line it was made from; calls that never return are logged right away. A call the kernel
+    /// restarts, like one our SIGSTOPs interrupt, is logged once, when it finally returns.
+    fn trace_syscall(&mut self, status: &Status) -> bool {
+        let (number, exited) = match status {
+            Status::Caught(CatchEvent::SyscallEntry(number), _) if self.trace_syscalls => (*number, false),
+            Status::Caught(CatchEvent::SyscallExit(number), _) if self.trace_syscalls => (*number, true),
+            _ => return false,
+        };
+        let infer = self.inferior.as_ref().unwrap();
+        let tid = infer.current_thread().1;
+        let rax = match infer.getregs() {
+            Ok(regs) => regs.rax,
+            Err(_) => return true,
+        };
+        // Forget about the calls of a process we no longer follow after a fork
+        let threads = infer.threads();
+        self.syscall_entries.retain(|entry| threads.iter().any(|(_, thread)| *thread == entry.tid));
+        if let Some(index) = self.syscall_entries.iter().position(|entry| entry.tid == tid) {
+            let mut entry = self.syscall_entries.remove(index);
+            if exited && entry.number == number {
+                if syscalls::is_restart(rax) {
+                    // Whether it is restarted shows once the thread enters its next call
+                    entry.restart = Some(rax);
+                    self.syscall_entries.push(entry);
+                } else {
+                    println!("{} = {}", entry.call, syscalls::format_return(number, rax));
+                }
+                return true;
+            }
+            let restarted = number == entry.number || syscalls::syscall_name(number) == Some("restart_syscall");
+            match entry.restart {
+                Some(_) if !exited && restarted => {
+                    entry.number = number;
+                    entry.restart = None;
+                    self.syscall_entries.push(entry);
+                    return true;
+                }
+                Some(code) => println!("{} = {}", entry.call, syscalls::format_return(entry.number, code)),
+                // The call returned while we were stepping
+                None => {}
+            }
+        }
+        let call = match self.describe_syscall(number) {
+            Ok(call) => call,
+            Err(_) => return true,
+        };
+        if exited {
+            println!("{} = {}", call, syscalls::format_return(number, rax));
+        } else if syscalls::returns(number) {
+            self.syscall_entries.push(SyscallEntry {
+                tid,
+                number,
+                call,
+                restart: None,
+            });
+        } else {
+            println!("{} = ?", call);
+        }
+        true
+    }
+
+    /// Logs the traced system calls that were still in progress when the inferior died
+    fn flush_syscall_entries(&mut self) {
+        for entry in self.syscall_entries.drain(..) {
+            match entry.restart {
+                Some(code) => println!("{} = {}", entry.call, syscalls::format_return(entry.number, code)),
+                None => println!("{} = ?", entry.call),
+            }
+        }
+    }
+
+    /// Implements `trace syscalls [on|off]`
+    fn trace_command(&mut self, args: &[String]) {
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        self.trace_syscalls = match args.as_slice() {
+            ["syscalls"] => {
+                let state = if self.trace_syscalls { "on" } else { "off" };
+                println!("Tracing of system calls is {}.", state);
+                return;
+            }
+            ["syscalls", "on"] => true,
+            ["syscalls", "off"] => false,
+            _ => {
+                println!("Usage: trace syscalls [on|off]");
+                return;
+            }
+        };
+        if !self.trace_syscalls {
+            self.syscall_entries.clear();
+        }
+        self.sync_catchpoints();
+    }
+
+    /// Finds the object a watchpoint is meant for. `*addr` watches an int at a raw address.
+    /// Before the program runs, only global variables have an address.
+    fn resolve_watch_target(&self, text: &str) -> Result<(usize, Type), String> {
+        if text.starts_with('*') {
+            if let Ok(expr::Expr::Integer(addr, _)) = expr::parse(text.split_at(1).1, &|_| false) {
+                let int_type = Type::new("int".to_string(), 4, TypeKind::Base(Encoding::Signed));
+                return Ok((addr as usize, int_type));
+            }
+        }
+        if self.inferior.is_none() {
+            return match self.dwarf_data.get_variable(0, text) {
+                    Some(var) => match var.location {
+                        Location::Address(addr) => Ok((addr, var.entity_type.clone())),
+                        _ => Err("The program is not being run.".to_string()),
+                    },
+                    None => Err(format!("No symbol \"{}\" in current context.", text)),
+            };
+        }
+        let evaluator = self.evaluator()?;
+        match evaluator.parse(text).and_then(|e| evaluator.evaluate(&e))? {
+            Value::Lvalue(addr, entity_type) => Ok((addr, entity_type)),
+            _ => Err(format!("Cannot watch constant value `{}'.", text)),
+        }
+    }
+
+    /// Assigns the enabled watchpoints to debug registers and programs them into the inferior
+    fn sync_watchpoints(&mut self) {
+        let mut slots = Vec::new();
+        self.watch_slots.clear();
+        for wp in self.watchpoints.iter().filter(|wp| wp.enabled) {
+            let access = match wp.kind {
+                WatchKind::Write => WatchAccess::Write,
+                WatchKind::Read | WatchKind::Access => WatchAccess::ReadWrite,
+            };
+            for (addr, len) in watch_chunks(wp.addr, wp.entity_type.size) {
+                slots.push((addr, len, access));
+                self.watch_slots.push(wp.number);
+            }
+        }
+        if let Some(infer) = self.inferior.as_mut() {
+            if let Err(e) = infer.set_watch_slots(&slots) {
+                println!("Cannot set hardware watchpoints: {}", e);
+            }
+        }
+    }
+
+    /// Programs the debug registers of a freshly started inferior and records the initial
+    /// values of the watched objects
+    fn install_watchpoints(&mut self) {
+        let infer = self.inferior.as_ref().unwrap();
+        for wp in self.watchpoints.iter_mut() {
+            wp.old_value = infer.read_memory(wp.addr, wp.entity_type.size, &self.breakpoints_map).ok();
+        }
+        self.sync_watchpoints();
+    }
+
+    /// Installs or removes the 0xcc at `addr`, depending on whether an enabled breakpoint still
+    /// refers to it. A running inferior is patched right away; otherwise the byte is written when
+    /// the next inferior starts.
+    fn update_trap(&mut self, addr: usize) {
+        let wanted = self.breakpoints.iter().any(|bp| bp.addr == addr && bp.enabled)
+            || self.entry_trap == Some(addr)
+            || self.library_trap == Some(addr)
+            || self.one_shots.iter().any(|one_shot| one_shot.addr == addr)
+            || self.watchpoints.iter().any(|wp| wp.scope.as_ref().map(|scope| scope.return_addr) == Some(addr));
+        let installed = self.breakpoints_map.contains_key(&addr);
+        if wanted && !installed {
+            let orig_byte = match self.inferior.as_mut() {
+                Some(infer) => match infer.write_byte(addr, 0xcc) {
+                    Ok(orig_byte) => orig_byte,
+                    Err(e) => {
+                        println!("Cannot insert breakpoint at {:#x}: {}", addr, e);
+                        return;
+                    }
+                },
+                None => 0,
+            };
+            self.breakpoints_map.insert(addr, Breakpoint::new(addr, orig_byte).unwrap());
+        } else if !wanted && installed {
+            let bp = self.breakpoints_map.remove(&addr).unwrap();
+            if let Some(infer) = self.inferior.as_mut() {
+                if let Err(e) = infer.write_byte(addr, bp.orig_byte) {
+                    println!("Cannot remove breakpoint at {:#x}: {}", addr, e);
+                }
+            }
+        }
+    }
+
+    /// Called when a freshly started inferior reaches its entry point. The dynamic loader has
+    /// mapped the shared libraries by now, so breakpoints in them can be installed as well.
+    fn reached_entry(&mut self, entry: usize) {
+        self.entry_trap = None;
+        self.load_libraries();
+        let infer = self.inferior.as_mut().unwrap();
+        infer.install_breakpoints(&mut self.breakpoints_map);
+        self.update_trap(entry);
+    }
+
+    /// Implements `handle <signal|all> [stop|nostop|print|noprint|pass|nopass]...`. As in gdb,
+    /// stop implies print and noprint implies nostop; ignore and noignore mean nopass and pass.
+    /// Prints the resulting policies.
+    fn handle_signals(&mut self, args: &[String]) {
+        let signals: Vec<Signal> = match args.first().map(|arg| arg.as_str()) {
+            None => {
+                println!("Argument required (signal and action to apply).");
+                return;
+            }
+            // The signals the debugger itself uses are left out of `all`
+            Some("all") => Signal::iterator()
+                .filter(|sig| *sig != Signal::SIGTRAP && *sig != Signal::SIGINT)
+                .collect(),
+            Some(arg) => match parse_signal(arg) {
+                Some(sig) => vec![sig],
+                None => {
+                    println!("Unrecognized or ambiguous flag word: \"{}\".", arg);
+                    return;
+                }
+            },
+        };
+        let keywords = ["stop", "nostop", "print", "noprint", "pass", "nopass", "ignore", "noignore"];
+        if let Some(keyword) = args[1..].iter().find(|arg| !keywords.contains(&arg.as_str())) {
+            println!("Unrecognized or ambiguous flag word: \"{}\".", keyword);
+            return;
+        }
+        if args.len() > 1 && signals == [Signal::SIGTRAP] {
+            println!("SIGTRAP is used by the debugger.");
+            return;
+        }
+        for sig in &signals {
+            let policy = self.signal_policies.get_mut(sig).unwrap();
+            for keyword in &args[1..] {
+                match keyword.as_str() {
+                    "stop" => {
+                        policy.stop = true;
+                        policy.print = true;
+                    }
+                    "nostop" => policy.stop = false,
+                    "print" => policy.print = true,
+                    "noprint" => {
+                        policy.print = false;
+                        policy.stop = false;
+                    }
+                    "pass" | "noignore" => policy.pass = true,
+                    _ => policy.pass = false,
+                }
+            }
+        }
+        if let Some(infer) = self.inferior.as_mut() {
+            infer.set_signal_policies(&self.signal_policies);
+        }
+        self.print_signal_table(&signals);
+    }
+
+    /// Implements `info signals`, for one signal or all of them
+    fn print_signals(&self, args: &[String]) {
+        match args.first() {
+            Some(arg) => match parse_signal(arg) {
+                Some(sig) => self.print_signal_table(&[sig]),
+                None => println!("Only signals 1-15 are valid as numeric signals."),
+            },
+            None => {
+                let signals: Vec<Signal> = Signal::iterator().collect();
+                self.print_signal_table(&signals);
+                println!("\nUse the \"handle\" command to change these tables.");
+            }
+        }
+    }
+
+    fn print_signal_table(&self, signals: &[Signal]) {
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        println!("{:<14}{:<6}{:<7}{:<17}Description", "Signal", "Stop", "Print", "Pass to program");
+        for sig in signals {
+            let policy = self.signal_policies[sig];
+            println!(
+                "{:<14}{:<6}{:<7}{:<17}{}",
+                sig.as_ref(),
+                yes_no(policy.stop),
+                yes_no(policy.print),
+                yes_no(policy.pass),
+                signal_description(*sig)
+            );
+        }
+    }
+
+    /// Implements `info sharedlibrary`
+    fn print_libraries(&self) {
+        if self.libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!("{:<20}{:<20}Shared Object Library", "From", "To");
+        for library in &self.libraries {
+            let (start, end) = library.dwarf_data.address_range();
+            println!("{:<#20x}{:<#20x}{}", start, end, library.path);
+        }
+    }
+
+    /// Implements `info breakpoints`, which lists watchpoints and catchpoints as well
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() && self.catchpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        let mut rows = Vec::new();
+        for bp in &self.breakpoints {
+            let mut what = String::new();
+            if let Some(func) = self.module(bp.addr).get_function_from_addr(bp.addr) {
+                what.push_str(&format!("in {} ", func));
+            }
+            if let Some(line) = self.module(bp.addr).get_line_from_addr(bp.addr) {
+                what.push_str(&format!("at {}", line));
+            }
+            let mut row = format!(
+                "{:<6}{:<5}{:<5}{:<#20x}{:<6}{}",
+                bp.number,
+                if bp.temporary { "del" } else { "keep" },
+                if bp.enabled { "y" } else { "n" },
+                bp.addr,
+                bp.hit_count,
+                what
+            );
+            if let Some(condition) = &bp.condition {
+                row.push_str(&format!("\n\tstop only if {}", condition));
+            }
+            if bp.ignore_count > 0 {
+                row.push_str(&format!(
+                    "\n\tWill ignore next {} crossings of breakpoint.",
+                    bp.ignore_count
+                ));
+            }
+            rows.push((bp.number, row));
+        }
+        for wp in &self.watchpoints {
+            let kind = match wp.kind {
+                WatchKind::Write => "hw",
+                WatchKind::Read => "read",
+                WatchKind::Access => "acc",
+            };
+            let row = format!(
+                "{:<6}{:<5}{:<5}{:<#20x}{:<6}{} watchpoint: {}",
+                wp.number,
+                "keep",
+                if wp.enabled { "y" } else { "n" },
+                wp.addr,
+                wp.hit_count,
+                kind,
+                wp.expr
+            );
+            rows.push((wp.number, row));
+        }
+        for cp in &self.catchpoints {
+            let row = format!(
+                "{:<6}{:<5}{:<5}{:<20}{:<6}catchpoint: {}",
+                cp.number,
+                "keep",
+                if cp.enabled { "y" } else { "n" },
+                "",
+                cp.hit_count,
+                cp.kind.describe()
+            );
+            rows.push((cp.number, row));
+        }
+        rows.sort_by_key(|(number, _)| *number);
+        println!("{:<6}{:<5}{:<5}{:<20}{:<6}What", "Num", "Disp", "Enb", "Address", "Hits");
+        for (_, row) in rows {
+            println!("{}", row);
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // Print prompt and get next line of user input
+            match self.readline.readline("(deet) ") {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return DebuggerCommand::Quit;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() == 0 {
+                        continue;
+                    }
+                    self.readline.add_history_entry(line.as_str());
+                    if let Err(err) = self.readline.save_history(&self.history_path) {
+                        println!(
+                            "Warning: failed to save history file at {}: {}",
+                            self.history_path, err
+                        );
+                    }
+                    let tokens: Vec<&str> = line.split_whitespace().collect();
+                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                        return cmd;
+                    } else {
+                        println!("Unrecognized command.");
+                    }
+                }
+            }
+        }
+    }
+
+    /// continue
+    /// using ptrace::cont
+    /// if inferior stopped at a breakpoint, Inferior::cont_exec rewinds %rip to the breakpoint
+    /// address so that the original instruction is executed on the next resume
+    /// breakpoints whose condition is false or whose ignore count is not used up yet, and
+    /// watchpoints whose value did not change, are passed over and the inferior is quietly resumed
+    fn continue_exec(&mut self) {
+        loop {
+            let infer = self.inferior.as_mut().unwrap();
+            let status = match infer.cont_exec(&self.breakpoints_map) {
+                Ok(status) => status,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let messages = match status {
+                Status::Execed => {
+                    self.follow_exec();
+                    match self.check_catchpoints(&status) {
+                        messages if messages.is_empty() => continue,
+                        messages => messages,
+                    }
+                }
+                Status::Stopped(Signal::SIGTRAP, rip) => match self.check_trap(rip) {
+                    Some(messages) if messages.is_empty() && !self.reached_one_shot(rip) => continue,
+                    Some(messages) => messages,
+                    None => Vec::new(),
+                },
+                _ => {
+                    let traced = self.trace_syscall(&status);
+                    match self.check_catchpoints(&status) {
+                        messages if messages.is_empty() && traced => continue,
+                        messages => messages,
+                    }
+                }
+            };
+            self.report_stop(status, true, &messages);
+            return;
+        }
+    }
+
+    /// Tells whether `rip` is where `until` or `advance` is headed, in the right thread and
+    /// frame
+    fn reached_one_shot(&self, rip: usize) -> bool {
+        let infer = self.inferior.as_ref().unwrap();
+        let rsp = match infer.getregs() {
+            Ok(regs) => regs.rsp as usize,
+            Err(_) => return true,
+        };
+        let thread = infer.current_thread().1;
+        self.one_shots.iter().any(|one_shot| {
+            one_shot.addr == rip
+                && one_shot.thread == thread
+                && !matches!(one_shot.frame_end, Some(frame_end) if rsp < frame_end)
+        })
+    }
+
+    /// Implements `advance <location>` and `until <location>`: runs until the location is
+    /// reached or the current function returns, whichever comes first
+    fn advance(&mut self, location: &str) {
+        let addr = match self.parse_location(location) {
+            Ok(addr) => addr,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        let infer = self.inferior.as_ref().unwrap();
+        let thread = infer.current_thread().1;
+        let mut one_shots = vec![OneShot {
+            addr,
+            thread,
+            frame_end: None,
+        }];
+        if let Ok(frame) = infer.innermost_frame() {
+            let dwarf_data = self.module(frame.pc());
+            if let Ok(frame_end) = infer.frame_base(&frame, dwarf_data, &self.breakpoints_map) {
+                if let Ok(return_addr) = infer.read_word(frame_end - 8) {
+                    one_shots.push(OneShot {
+                        addr: return_addr,
+                        thread,
+                        frame_end: Some(frame_end),
+                    });
+                }
+            }
+        }
+        for one_shot in one_shots {
+            let addr = one_shot.addr;
+            self.one_shots.push(one_shot);
+            self.update_trap(addr);
+        }
+        self.continue_exec();
+    }
+
+    /// Implements `until` without an argument. It works like next, but keeps stepping while it
+    /// lands on lines before the current one, so that a loop is run to its end. It stops at a
+    /// later line of the current frame, or once the frame returns.
+    fn until_next_line(&mut self) {
+        let infer = self.inferior.as_ref().unwrap();
+        let frame = match infer.innermost_frame() {
+            Ok(frame) => frame,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let pc = frame.pc();
+        let start_line = self.module(pc).get_line_from_addr(pc);
+        let frame_end = infer.frame_base(&frame, self.module(pc), &self.breakpoints_map).ok();
+        let library_trap = self.lift_library_trap();
+        let infer = self.inferior.as_mut().unwrap();
+        let dwarf_data = find_module(&self.dwarf_data, &self.libraries, pc);
+        let result = loop {
+            let status = match infer.step_line(dwarf_data, &self.breakpoints_map, false) {
+                Ok(status) => status,
+                Err(e) => break Err(e),
+            };
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip,
+                _ => break Ok(status),
+            };
+            if self.breakpoints_map.contains_key(&rip) || infer.watch_hits().unwrap_or(0) != 0 {
+                break Ok(status);
+            }
+            let rsp = infer.getregs().map(|regs| regs.rsp as usize).unwrap_or(usize::MAX);
+            let returned = !matches!(frame_end, Some(frame_end) if rsp < frame_end);
+            let past_start = match (start_line.as_ref(), dwarf_data.get_line_from_addr(rip)) {
+                (Some(start_line), Some(line)) => line.file != start_line.file || line.number > start_line.number,
+                _ => true,
+            };
+            if returned || past_start {
+                break Ok(status);
+            }
+        };
+        self.restore_library_trap(library_trap, &result);
+        match result {
+            Ok(status) => self.report_status(status, false),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Works out what a SIGTRAP at `rip` means to the user. Returns None if none of our
+    /// breakpoints or watchpoints caused it. Otherwise returns the announcements of the ones that
+    /// triggered, which may be none at all if they all let the inferior go on.
+    fn check_trap(&mut self, rip: usize) -> Option<Vec<String>> {
+        let infer = self.inferior.as_ref().unwrap();
+        let fired = infer.watch_hits().unwrap_or(0);
+        let at_breakpoint = self.breakpoints_map.contains_key(&rip);
+        if fired == 0 && !at_breakpoint {
+            return None;
+        }
+        if at_breakpoint && self.entry_trap == Some(rip) {
+            self.reached_entry(rip);
+        } else if at_breakpoint && self.library_trap == Some(rip) {
+            self.load_libraries();
+        }
+        let mut messages = Vec::new();
+        if at_breakpoint {
+            let dwarf_data = self.module(rip);
+            let func = dwarf_data
+                .get_function_from_addr(rip)
+                .or_else(|| dwarf_data.get_symbol_from_addr(rip).map(|name| name.to_string()));
+            for number in self.check_breakpoints(rip) {
+                let temporary = self.breakpoints.iter().any(|bp| bp.number == number && bp.temporary);
+                let kind = if temporary { "Temporary breakpoint" } else { "Breakpoint" };
+                messages.push(match &func {
+                    Some(func) => format!("{} {}, {}", kind, number, func),
+                    None => format!("{} {}, {:#x}", kind, number, rip),
+                });
+                if temporary {
+                    self.delete_breakpoint(number);
+                }
+            }
+            let infer = self.inferior.as_ref().unwrap();
+            let thread = infer.current_thread().1;
+            let rsp = infer.getregs().map(|regs| regs.rsp as usize).unwrap_or(0);
+            while let Some(index) = self.watchpoints.iter().position(|wp| match &wp.scope {
+                Some(scope) => scope.return_addr == rip && scope.thread == thread && rsp >= scope.frame_end,
+                None => false,
+            }) {
+                messages.push(left_scope(self.watchpoints[index].number));
+                self.delete_watchpoint(index);
+            }
+        }
+        if fired != 0 {
+            if let Err(e) = self.inferior.as_ref().unwrap().clear_watch_hits() {
+                println!("{}", e);
+            }
+            messages.extend(self.check_watchpoints(fired, rip));
+        }
+        Some(messages)
+    }
+
+    /// Compares the watchpoints behind the debug registers set in `fired` with their last known
+    /// value, and describes the ones that should stop the inferior. A watch only stops on a
+    /// change; since x86 cannot trap on reads alone, an rwatch ignores accesses that changed
+    /// the value. The access happened in the instruction right before `rip`.
+    fn check_watchpoints(&mut self, fired: u64, rip: usize) -> Vec<String> {
+        let infer = self.inferior.as_ref().unwrap();
+        let dwarf_data = &self.dwarf_data;
+        let breakpoints_map = &self.breakpoints_map;
+        let read_memory = |addr, len| infer.read_memory(addr, len, breakpoints_map).ok();
+        let numbers: Vec<usize> = (0..self.watch_slots.len())
+            .filter(|slot| fired & (1 << slot) != 0)
+            .map(|slot| self.watch_slots[slot])
+            .collect();
+        let location = match find_module(dwarf_data, &self.libraries, rip - 1).get_line_from_addr(rip - 1) {
+            Some(line) => format!("{}:{}", line.file, line.number),
+            None => format!("{:#x}", rip - 1),
+        };
+        let mut messages = Vec::new();
+        for wp in self.watchpoints.iter_mut().filter(|wp| numbers.contains(&wp.number)) {
+            let new_value = match read_memory(wp.addr, wp.entity_type.size) {
+                Some(new_value) => new_value,
+                None => continue,
+            };
+            let changed = wp.old_value.as_ref() != Some(&new_value);
+            let header = format!("\n{} {}: {}\n", wp.kind.describe(), wp.number, wp.expr);
+            let new_text = dwarf_data.format_value(&wp.entity_type, &new_value, &read_memory);
+            let message = match (wp.kind, changed) {
+                (WatchKind::Write, false) => continue,
+                (WatchKind::Read, true) => {
+                    wp.old_value = Some(new_value);
+                    continue;
+                }
+                (_, true) => {
+                    let old_text = match &wp.old_value {
+                        Some(old_value) => {
+                            dwarf_data.format_value(&wp.entity_type, old_value, &read_memory)
+                        }
+                        None => "<unreadable>".to_string(),
+                    };
+                    format!(
+                        "{}\nOld value = {}\nNew value = {}\nChanged at {}",
+                        header, old_text, new_text, location
+                    )
+                }
+                (_, false) => format!("{}\nValue = {}\nAccessed at {}", header, new_text, location),
+            };
+            wp.hit_count += 1;
+            wp.old_value = Some(new_value);
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Decides which enabled breakpoints at `rip` trigger: their condition must hold and their
+    /// ignore count must be used up. Every breakpoint whose condition holds counts as hit. A
+    /// condition that cannot be evaluated triggers the breakpoint, so that the user gets to see
+    /// why. Returns the numbers of the triggered breakpoints.
+    fn check_breakpoints(&mut self, rip: usize) -> Vec<usize> {
+        let infer = self.inferior.as_ref().unwrap();
+        let frame = match infer.innermost_frame() {
+            Ok(frame) => frame,
+            Err(_) => return Vec::new(),
+        };
+        let evaluator = Evaluator {
+            process: infer,
+            frame,
+            dwarf_data: find_module(&self.dwarf_data, &self.libraries, rip),
+            modules: std::iter::once(&self.dwarf_data)
+                .chain(self.libraries.iter().map(|library| &library.dwarf_data))
+                .collect(),
+            breakpoints: &self.breakpoints_map,
+        };
+        let mut hits = Vec::new();
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.addr == rip && bp.enabled) {
+            let satisfied = match &bp.condition {
+                Some(condition) => match evaluator.parse(condition).and_then(|e| evaluator.is_true(&e)) {
+                    Ok(satisfied) => satisfied,
+                    Err(message) => {
+                        println!("Error in testing condition for breakpoint {}:", bp.number);
+                        println!("{}", message);
+                        true
+                    }
+                },
+                None => true,
+            };
+            if !satisfied {
+                continue;
+            }
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+            } else {
+                hits.push(bp.number);
+            }
+        }
+        hits
+    }
+
+    /// Prints how the inferior stopped, and forgets about it if it is gone. A SIGTRAP is only
+    /// announced when `announce_trap` is set, since stepping produces one after every step. A
+    /// new program the inferior executed is run like after `run`, unless an exec catchpoint
+    /// stops it. So is a system call that is only traced, which the inferior stops at when
+    /// continued by a step out of code without line information.
+    fn report_status(&mut self, status: Status, announce_trap: bool) {
+        let messages = match status {
+            Status::Execed => {
+                self.follow_exec();
+                let messages = self.check_catchpoints(&status);
+                if messages.is_empty() {
+                    self.continue_exec();
+                    return;
+                }
+                messages
+            }
+            Status::Stopped(Signal::SIGTRAP, rip) => self.check_trap(rip).unwrap_or_default(),
+            _ => {
+                let traced = self.trace_syscall(&status);
+                let messages = self.check_catchpoints(&status);
+                if messages.is_empty() && traced {
+                    self.continue_exec();
+                    return;
+                }
+                messages
+            }
+        };
+        self.report_stop(status, announce_trap, &messages);
+    }
+
+    /// Does the printing for report_status, given the announcements of the breakpoints and
+    /// watchpoints that triggered the stop
+    fn report_stop(&mut self, status: Status, announce_trap: bool, messages: &[String]) {
+        self.selected_frame = 0;
+        for one_shot in std::mem::take(&mut self.one_shots) {
+            self.update_trap(one_shot.addr);
+        }
+        match status {
+            Status::Exited(exit_code) => {
+                self.flush_syscall_entries();
+                self.leave_watch_scopes();
+                println!("Child exited (status {})", exit_code);
+                self.take_held_process();
+            }
+            Status::Signaled(signal) => {
+                self.flush_syscall_entries();
+                self.leave_watch_scopes();
+                println!("Child terminated (signal {})", signal);
+                self.take_held_process();
+            }
+            // An exec catchpoint stopped the new program, which report_status and continue_exec
+            // have set up already
+            Status::Execed => {
+                for message in messages {
+                    println!("{}", message);
+                }
+                if let Ok(regs) = self.inferior.as_ref().unwrap().getregs() {
+                    self.print_location(regs.rip as usize);
+                }
+            }
+            Status::Stopped(_, rip) | Status::Caught(_, rip) => {
+                if let Some((number, tid)) = self.inferior.as_mut().unwrap().take_thread_switch() {
+                    println!("[Switching to thread {} (LWP {})]", number, tid);
+                }
+                // A signal catchpoint reports the signal itself
+                if let Status::Stopped(signal, _) = status {
+                    if messages.is_empty() && (announce_trap || signal != Signal::SIGTRAP) {
+                        println!("Child stopped (signal {})", signal);
+                    }
+                }
+                for message in messages {
+                    println!("{}", message);
+                }
+                self.print_location(rip);
+            }
+        }
+    }
+
+    /// Returns the selected frame of the live inferior or the core dump
+    fn selected_frame(&self) -> Result<Frame, String> {
+        let frames = self
+            .process()
+            .unwrap()
+            .unwind_stack(&self.modules())
+            .map_err(|e| e.to_string())?;
+        Ok(*frames.get(self.selected_frame).unwrap_or(frames.last().unwrap()))
+    }
+
+    /// Returns an evaluator for expressions in the selected frame
+    fn evaluator(&self) -> Result<Evaluator<'_>, String> {
+        let frame = self.selected_frame()?;
+        Ok(Evaluator {
+            process: self.process().unwrap(),
+            frame,
+            dwarf_data: self.module(frame.pc()),
+            modules: self.modules(),
+            breakpoints: &self.breakpoints_map,
+        })
+    }
+
+    /// Evaluates an expression in the selected frame and prints its value
+    fn print_expression(&self, text: &str) {
+        let value = self.evaluator().and_then(|evaluator| {
+            let value = evaluator.parse(text).and_then(|e| evaluator.evaluate(&e))?;
+            self.format_value(&evaluator, value)
+        });
+        match value {
+            Ok(value) => println!("{} = {}", text, value),
+            Err(message) => println!("{}", message),
+        }
+    }
+
+    /// Formats the result of an evaluation for display, reading lvalues from memory
+    fn format_value(&self, evaluator: &Evaluator, value: Value) -> Result<String, String> {
+        let read_memory = |addr, len| evaluator.read(addr, len).ok();
+        match value {
+            Value::Lvalue(addr, entity_type) => {
+                let bytes = evaluator.read(addr, entity_type.size)?;
+                Ok(evaluator.dwarf_data.format_value(&entity_type, &bytes, &read_memory))
+            }
+            Value::Pointer(addr, pointee) => {
+                // Functions and the instruction pointer show where in the function they point
+                if pointee.kind == TypeKind::Function {
+                    let type_name = pointee.name.replace("()", "(*)()");
+                    return Ok(match self.symbolize(addr) {
+                        Some(name) => format!("({}) {:#x} <{}>", type_name, addr, name),
+                        None => format!("({}) {:#x}", type_name, addr),
+                    });
+                }
+                let stars = if pointee.name.ends_with('*') { "*" } else { " *" };
+                let type_name = format!("{}{}", pointee.name, stars);
+                let text = evaluator.dwarf_data.format_pointer(&type_name, &pointee, addr, &read_memory, true);
+                match self.symbolize_data(addr) {
+                    Some(name) if !pointee.is_char() => Ok(format!("{} <{}>", text, name)),
+                    _ => Ok(text),
+                }
+            }
+            Value::Integer(value, entity_type) => {
+                let size = entity_type.strip().size.min(8);
+                let bytes = value.to_le_bytes();
+                Ok(evaluator.dwarf_data.format_value(&entity_type, &bytes[..size], &read_memory))
+            }
+            Value::Float(value, entity_type) if entity_type.strip().size == 4 => Ok(format_float(value as f32)),
+            Value::Float(value, _) => Ok(format_float(value)),
+            Value::Bytes(bytes, entity_type) => {
+                Ok(evaluator.dwarf_data.format_value(&entity_type, &bytes, &read_memory))
+            }
+            Value::OptimizedOut => Ok("<optimized out>".to_string()),
+        }
+    }
+
+    /// Implements `info locals` and `info args` for the selected frame
+    fn print_frame_variables(&self, parameters: bool) {
+        if self.process().is_none() {
+            println!("No frame selected.");
+            return;
+        }
+        let evaluator = match self.evaluator() {
+            Ok(evaluator) => evaluator,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        let func = match evaluator.dwarf_data.get_function_containing_addr(evaluator.frame.pc()) {
+            Some(func) => func,
+            None => {
+                println!("No symbol table info available.");
+                return;
+            }
+        };
+        let mut variables = func
+            .variables
+            .iter()
+            .filter(|var| var.is_parameter == parameters)
+            .peekable();
+        if variables.peek().is_none() {
+            println!("{}", if parameters { "No arguments." } else { "No locals." });
+        }
+        for var in variables {
+            let value = evaluator
+                .evaluate(&expr::Expr::Variable(var.name.clone()))
+                .and_then(|value| self.format_value(&evaluator, value));
+            match value {
+                Ok(value) => println!("{} = {}", var.name, value),
+                Err(message) => println!("{} = <{}>", var.name, message),
+            }
+        }
+    }
+
+    /// Parses the optional count of up and down, or the frame number of frame, which defaults
+    /// to 1. Prints an error and returns None if the argument is bad or there is no stack.
+    fn parse_frame_count(&self, args: &[String]) -> Option<usize> {
+        if self.process().is_none() {
+            println!("No stack.");
+            return None;
+        }
+        match args.first().map(|arg| arg.parse::<usize>()) {
+            Some(Ok(count)) => Some(count),
+            Some(Err(_)) => {
+                println!("Invalid number \"{}\".", args[0]);
+                None
+            }
+            None => Some(1),
+        }
+    }
+
+    /// Selects frame `target` and prints it, or just prints the selected frame for None. Going
+    /// up past the outermost frame stops there; `up` complains if it cannot move at all.
+    fn select_frame(&mut self, target: Option<usize>, up: bool) {
+        let frames = match self.process().unwrap().unwind_stack(&self.modules()) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if let Some(target) = target {
+            if up && self.selected_frame + 1 >= frames.len() {
+                println!("Initial frame selected; you cannot go up.");
+                return;
+            }
+            if !up && target >= frames.len() {
+                println!("No frame at level {}.", target);
+                return;
+            }
+            self.selected_frame = target.min(frames.len() - 1);
+        }
+        let number = self.selected_frame.min(frames.len() - 1);
+        println!("{}", self.describe_frame(number, &frames[number]));
+    }
+
+    /// Prints one line per frame of the call stack
+    fn print_backtrace(&self) {
+        let frames = match self.process().unwrap().unwind_stack(&self.modules()) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        for (number, frame) in frames.iter().enumerate() {
+            println!("{}", self.describe_frame(number, frame));
+        }
+    }
+
+    /// Prints one line per thread of the inferior, marking the current one with `*`
+    fn print_threads(&mut self) {
+        let (current, threads) = match self.inferior.as_ref() {
+            Some(infer) => (infer.current_thread().0, infer.threads()),
+            None => {
+                println!("No threads.");
+                return;
+            }
+        };
+        println!("  {:<4} {:<16} Frame", "Id", "Target Id");
+        for (number, tid) in threads {
+            self.inferior.as_mut().unwrap().select_thread(number);
+            let frame = match self.inferior.as_ref().unwrap().innermost_frame() {
+                Ok(frame) => self.describe_pc(&frame),
+                Err(e) => e.to_string(),
+            };
+            let marker = if number == current { '*' } else { ' ' };
+            println!("{} {:<4} {:<16} {}", marker, number, format!("LWP {}", tid), frame);
+        }
+        self.inferior.as_mut().unwrap().select_thread(current);
+    }
+
+    /// Implements `thread [N]`: switches to thread N and shows its innermost frame, or tells
+    /// which thread is current
+    fn select_thread(&mut self, args: &[String]) {
+        let infer = match self.inferior.as_mut() {
+            Some(infer) => infer,
+            None => {
+                println!("No thread selected");
+                return;
+            }
+        };
+        let number = match args.first() {
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(number) => number,
+                Err(_) => {
+                    println!("Invalid thread ID: {}", arg);
+                    return;
+                }
+            },
+            None => {
+                let (number, tid) = infer.current_thread();
+                println!("[Current thread is {} (LWP {})]", number, tid);
+                return;
+            }
+        };
+        if !infer.select_thread(number) {
+            println!("Unknown thread {}.", number);
+            return;
+        }
+        println!("[Switching to thread {} (LWP {})]", number, infer.current_thread().1);
+        self.selected_frame = 0;
+        self.select_frame(None, false);
+    }
+
+    /// Formats the backtrace line of a frame. Frames without debug information show the symbol
+    /// name if there is one, or `??`.
+    fn describe_frame(&self, number: usize, frame: &Frame) -> String {
+        format!("#{:<3}{}", number, self.describe_pc(frame))
+    }
+
+    /// Formats the function and source line a frame is executing. Like in gdb, the address
+    /// comes first in the outer frames and wherever there is no line information.
+    fn describe_pc(&self, frame: &Frame) -> String {
+        let pc = frame.pc();
+        let dwarf_data = self.module(pc);
+        match (
+            dwarf_data.get_function_from_addr(pc),
+            dwarf_data.get_line_from_addr(pc),
+        ) {
+            (Some(func), Some(line)) if frame.innermost => format!("{} ({}:{})", func, line.file, line.number),
+            (Some(func), Some(line)) => {
+                format!("{:#018x} in {} ({}:{})", frame.regs.rip, func, line.file, line.number)
+            }
+            _ => format!(
+                "{:#018x} in {} ()",
+                frame.regs.rip,
+                dwarf_data.get_symbol_from_addr(pc).unwrap_or("??")
+            ),
+        }
+    }
+
+    /// Implements `x/<count><format><size> <address>`. The format and size default to the ones
+    /// used last, and without an address, `x` carries on where the previous one stopped.
+    fn examine_memory(&mut self, format: &str, text: &str) {
+        let (count, format, size) = match self.parse_examine_format(format) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        self.examine_format = format;
+        if format != 's' && format != 'i' {
+            self.examine_size = size;
+        }
+        let next = {
+            let evaluator = match self.evaluator() {
+                Ok(evaluator) => evaluator,
+                Err(message) => {
+                    println!("{}", message);
+                    return;
+                }
+            };
+            let addr = if text.is_empty() {
+                self.next_examine.unwrap()
+            } else {
+                match evaluator.parse(text).and_then(|e| evaluator.evaluate_address(&e)) {
+                    Ok(addr) => addr,
+                    Err(message) => {
+                        println!("{}", message);
+                        return;
+                    }
+                }
+            };
+            match format {
+                'i' => self.examine_instructions(&evaluator, addr, count),
+                's' => self.examine_strings(&evaluator, addr, count),
+                _ => self.examine_units(&evaluator, addr, count, format, size),
+            }
+        };
+        self.next_examine = Some(next);
+    }
+
+    /// Splits the `/<count><format><size>` suffix of `x` into its parts, filling in defaults.
+    /// The format and size letters may come in either order.
+    fn parse_examine_format(&self, text: &str) -> Result<(usize, char, usize), String> {
+        let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let count = if digits == 0 {
+            1
+        } else {
+            text[..digits]
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid number \"{}\".", &text[..digits]))?
+        };
+        let mut format = None;
+        let mut size = None;
+        for letter in text[digits..].chars() {
+            match letter {
+                'b' => size = Some(1),
+                'h' => size = Some(2),
+                'w' => size = Some(4),
+                'g' => size = Some(8),
+                'x' | 'd' | 'u' | 'o' | 't' | 'c' | 'a' | 'f' | 's' | 'i' => format = Some(letter),
+                _ => return Err(format!("Invalid format letter '{}'.", letter)),
+            }
+        }
+        let format = format.unwrap_or(self.examine_format);
+        let size = match (format, size) {
+            (_, Some(size)) if format != 'a' && !(format == 'f' && size < 4) => size,
+            ('a', _) => 8,
+            ('c', _) | ('s', _) => 1,
+            ('f', _) if self.examine_size < 4 => 8,
+            _ => self.examine_size,
+        };
+        Ok((count, format, size))
+    }
+
+    /// Prints `count` numbers of `size` bytes each, several to a line. Returns the address
+    /// after the last one.
+    fn examine_units(&self, evaluator: &Evaluator, addr: usize, count: usize, format: char, size: usize) -> usize {
+        let per_line = match (format, size) {
+            ('c', _) => 8,
+            ('a', _) | (_, 8) => 2,
+            (_, 4) => 4,
+            _ => 8,
+        };
+        let mut curr_addr = addr;
+        for line_start in (0..count).step_by(per_line) {
+            let mut line = format!("{:#x}", curr_addr);
+            if let Some(name) = self.symbolize_data(curr_addr) {
+                line.push_str(&format!(" <{}>", name));
+            }
+            line.push(':');
+            for _ in line_start..count.min(line_start + per_line) {
+                let bytes = match evaluator.read(curr_addr, size) {
+                    Ok(bytes) => bytes,
+                    Err(message) => {
+                        println!("{}\t{}", line, message);
+                        return curr_addr;
+                    }
+                };
+                line.push('\t');
+                line.push_str(&self.format_unit(&bytes, format));
+                curr_addr += size;
+            }
+            println!("{}", line);
+        }
+        curr_addr
+    }
+
+    /// Formats one number read by `x` in the given format letter
+    fn format_unit(&self, bytes: &[u8], format: char) -> String {
+        let size = bytes.len();
+        let value = read_unsigned(bytes);
+        match format {
+            'd' => read_signed(bytes).to_string(),
+            'u' => value.to_string(),
+            'o' if value == 0 => "0".to_string(),
+            'o' => format!("0{:o}", value),
+            't' => format!("{:0width$b}", value, width = size * 8),
+            'c' => format!("{} {}", read_signed(bytes), format_char(bytes[0])),
+            'f' => read_float(bytes).to_string(),
+            'a' => match self.symbolize(value as usize) {
+                Some(name) => format!("{:#x} <{}>", value, name),
+                None => format!("{:#x}", value),
+            },
+            _ => format!("{:#0width$x}", value, width = size * 2 + 2),
+        }
+    }
+
+    /// Prints `count` NUL-terminated strings, one per line. Returns the address after the last
+    /// one.
+    fn examine_strings(&self, evaluator: &Evaluator, mut addr: usize, count: usize) -> usize {
+        for _ in 0..count {
+            let mut bytes = Vec::new();
+            let mut terminated = false;
+            while bytes.len() < MAX_EXAMINE_STRING_LENGTH {
+                match evaluator.read(addr + bytes.len(), 1) {
+                    Ok(byte) if byte[0] == 0 => {
+                        terminated = true;
+                        break;
+                    }
+                    Ok(byte) => bytes.push(byte[0]),
+                    Err(message) => {
+                        println!("{:#x}:\t{}", addr, message);
+                        return addr;
+                    }
+                }
+            }
+            let mut line = format!("{:#x}", addr);
+            if let Some(name) = self.symbolize_data(addr) {
+                line.push_str(&format!(" <{}>", name));
+            }
+            let ellipsis = if terminated { "" } else { "..." };
+            println!("{}:\t{}{}", line, format_string(&bytes), ellipsis);
+            addr += bytes.len() + terminated as usize;
+        }
+        addr
+    }
+
+    /// Implements `x/<count>i`, marking the selected frame's pc with `=>`. Returns the address
+    /// after the last instruction.
+    fn examine_instructions(&self, evaluator: &Evaluator, mut addr: usize, count: usize) -> usize {
+        let pc = evaluator.frame.regs.rip as usize;
+        for _ in 0..count {
+            let bytes = match evaluator.read(addr, MAX_INSTRUCTION_LENGTH) {
+                Ok(bytes) => bytes,
+                Err(message) => {
+                    println!("   {:#018x}:\t{}", addr, message);
+                    return addr;
+                }
+            };
+            let instruction = disassembler::decode(&bytes, addr).remove(0);
+            let marker = if addr == pc { "=> " } else { "   " };
+            println!("{}{}", marker, self.format_instruction(&instruction, None));
+            addr += instruction.len;
+        }
+        addr
+    }
+
+    /// Implements `disassemble`. Without an argument, the function around the selected
+    /// frame's pc is listed; otherwise the function with the given name or around the given
+    /// address, or `<start>,<length>` bytes. Instructions are grouped by source line, and `=>`
+    /// marks the selected frame's pc.
+    fn disassemble(&self, text: &str) {
+        let evaluator = match self.evaluator() {
+            Ok(evaluator) => evaluator,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        let evaluate_address = |text: &str| evaluator.parse(text).and_then(|e| evaluator.evaluate_address(&e));
+        let range = if let Some(comma) = text.find(',') {
+            let start = evaluate_address(&text[..comma]);
+            let len = evaluate_address(text[comma + 1..].trim().trim_start_matches('+'));
+            match (start, len) {
+                (Ok(start), Ok(len)) => Ok((None, start, start + len)),
+                (Err(message), _) | (_, Err(message)) => Err(message),
+            }
+        } else if text.is_empty() {
+            self.function_range(evaluator.frame.pc())
+                .map(|(name, start, end)| (Some(name), start, end))
+                .ok_or_else(|| "No function contains program counter for selected frame.".to_string())
+        } else {
+            match self.function_range_by_name(text) {
+                Some((name, start, end)) => Ok((Some(name), start, end)),
+                None => evaluate_address(text).and_then(|addr| {
+                    self.function_range(addr)
+                        .map(|(name, start, end)| (Some(name), start, end))
+                        .ok_or_else(|| "No function contains specified address.".to_string())
+                }),
+            }
+        };
+        let (name, start, end) = match range {
+            Ok(range) => range,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        let bytes = match evaluator.read(start, end - start) {
+            Ok(bytes) => bytes,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        match &name {
+            Some(name) => println!("Dump of assembler code for function {}:", name),
+            None => println!("Dump of assembler code from {:#x} to {:#x}:", start, end),
+        }
+        let pc = evaluator.frame.regs.rip as usize;
+        let mut last_line = None;
+        for instruction in disassembler::decode(&bytes, start) {
+            // Print the source line whenever it changes
+            if let Some(line) = self.module(instruction.addr).get_line_from_addr(instruction.addr) {
+                let line = line.to_string();
+                if last_line.as_ref() != Some(&line) {
+                    println!("{}", line);
+                    last_line = Some(line);
+                }
+            }
+            let marker = if instruction.addr == pc { "=> " } else { "   " };
+            let function_start = name.as_ref().map(|_| start);
+            println!("{}{}", marker, self.format_instruction(&instruction, function_start));
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// Formats one line of a disassembly listing. In the listing of a whole function,
+    /// addresses are shown relative to `function_start`, elsewhere relative to the function
+    /// around them. Jump and call targets are annotated the same way.
+    fn format_instruction(&self, instruction: &DecodedInstruction, function_start: Option<usize>) -> String {
+        let mut text = format!("{:#018x}", instruction.addr);
+        match function_start {
+            Some(start) => text.push_str(&format!(" <+{}>", instruction.addr - start)),
+            None => {
+                if let Some(name) = self.symbolize(instruction.addr) {
+                    text.push_str(&format!(" <{}>", name));
+                }
+            }
+        }
+        text.push_str(":\t");
+        text.push_str(&instruction.text);
+        if let Some(name) = instruction.branch_target.and_then(|target| self.symbolize(target)) {
+            text.push_str(&format!(" <{}>", name));
+        }
+        text
+    }
+
+    /// Returns the name and address range of the function around `addr`, from the debug
+    /// information or else the symbol table
+    fn function_range(&self, addr: usize) -> Option<(String, usize, usize)> {
+        let dwarf_data = self.module(addr);
+        match dwarf_data.get_function_containing_addr(addr) {
+            Some(func) => Some((func.name.clone(), func.address, func.address + func.text_length)),
+            None => dwarf_data
+                .get_symbol_containing_addr(addr)
+                .filter(|(_, _, size)| *size > 0)
+                .map(|(name, start, size)| (name.to_string(), start, start + size)),
+        }
+    }
+
+    /// Like function_range, but looks the function up by name
+    fn function_range_by_name(&self, name: &str) -> Option<(String, usize, usize)> {
+        self.modules().into_iter().find_map(|dwarf_data| {
+            match dwarf_data.find_functions(None, name).first() {
+                Some((_, func)) => Some((func.address, func.address + func.text_length)),
+                None => dwarf_data
+                    .get_symbol(name)
+                    .filter(|(_, size)| *size > 0)
+                    .map(|(start, size)| (start, start + size)),
+            }
+            .map(|(start, end)| (name.to_string(), start, end))
+        })
+    }
+
+    /// Describes an address relative to the function or global variable around it, e.g.
+    /// `total` or `numbers+8`
+    fn symbolize_data(&self, addr: usize) -> Option<String> {
+        self.symbolize(addr).or_else(|| {
+            let (name, start, _) = self.module(addr).get_object_containing_addr(addr)?;
+            if addr == start {
+                Some(name.to_string())
+            } else {
+                Some(format!("{}+{}", name, addr - start))
+            }
+        })
+    }
+
+    /// Describes an address relative to the function around it, e.g. `add+4`
+    fn symbolize(&self, addr: usize) -> Option<String> {
+        let (name, start, _) = self.function_range(addr)?;
+        if addr == start {
+            Some(name)
+        } else {
+            Some(format!("{}+{}", name, addr - start))
+        }
+    }
+
+    /// Implements `set var <lvalue> = <expression>`, which writes the value converted to the
+    /// lvalue's type, and `set $<register> = <expression>`
+    fn assign(&mut self, text: &str) {
+        let (target, value) = match split_assignment(text) {
+            Some(parts) => parts,
+            None => {
+                println!("Usage: set [var] <variable|$register> = <expression>");
+                return;
+            }
+        };
+        if !target.starts_with('$') {
+            let write = self.evaluator().and_then(|evaluator| {
+                let target = evaluator.parse(target)?;
+                let value = evaluator.parse(value)?;
+                evaluator.assignment(&target, &value)
+            });
+            let result = write.and_then(|(addr, bytes)| {
+                self.write_memory(addr, &bytes)
+                    .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+            });
+            if let Err(message) = result {
+                println!("{}", message);
+            }
+            return;
+        }
+        let name = &target[1..];
+        if self.selected_frame != 0 {
+            println!("Registers can only be changed in the innermost frame.");
+            return;
+        }
+        let evaluator = match self.evaluator() {
+            Ok(evaluator) => evaluator,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        if register_value(&evaluator.frame.regs, name).is_none() {
+            println!("Invalid register `{}'", name);
+            return;
+        }
+        let result = evaluator.parse(value).and_then(|e| evaluator.evaluate_integer(&e)).and_then(|value| {
+            self.inferior
+                .as_ref()
+                .unwrap()
+                .set_register(name, value as u64)
+                .map_err(|e| e.to_string())
+        });
+        if let Err(message) = result {
+            println!("{}", message);
+        }
+    }
+
+    /// Writes to the inferior's memory. Where a breakpoint is installed, the 0xcc stays in place
+    /// and the new byte is what gets restored when the breakpoint goes away.
+    fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        for (i, byte) in bytes.iter().enumerate() {
+            match self.breakpoints_map.get_mut(&(addr + i)) {
+                Some(bp) => bp.orig_byte = *byte,
+                None => {
+                    inferior.write_byte(addr + i, *byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Implements `info registers [<name>...]` and `info all-registers [<name>...]`, showing the
+    /// selected frame. all-registers adds the x87 and SSE state, which can also be asked for by
+    /// name.
+    fn print_registers(&self, names: &[String], all: bool) {
+        if self.process().is_none() {
+            println!("The program has no registers now.");
+            return;
+        }
+        let regs = match self.selected_frame() {
+            Ok(frame) => frame.regs,
+            Err(message) => {
+                println!("{}", message);
+                return;
+            }
+        };
+        let mut rows: Vec<(String, String)> = REGISTER_NAMES
+            .iter()
+            .map(|name| (name.to_string(), self.format_register(name, register_value(&regs, name).unwrap())))
+            .collect();
+        if all || !names.is_empty() {
+            match self.process().unwrap().getfpregs() {
+                Ok(fpregs) => rows.extend(fp_register_rows(&fpregs)),
+                Err(e) if all => println!("{}", e),
+                Err(_) => {}
+            }
+        }
+        if names.is_empty() {
+            for (name, value) in &rows {
+                println!("{:<15}{}", name, value);
+            }
+            return;
+        }
+        for name in names {
+            let name = name.trim_start_matches('$');
+            let value = match register_value(&regs, name) {
+                Some(value) => self.format_register(name, value),
+                None => match rows.iter().find(|(row_name, _)| row_name == name) {
+                    Some((_, value)) => value.clone(),
+                    None => {
+                        println!("Invalid register `{}'", name);
+                        return;
+                    }
+                },
+            };
+            println!("{:<15}{}", name, value);
+        }
+    }
+
+    /// Formats a general purpose register as the hexadecimal and natural columns of
+    /// `info registers`
+    fn format_register(&self, name: &str, value: u64) -> String {
+        let natural = match name {
+            "rip" | "pc" => match self.symbolize(value as usize) {
+                Some(name) => format!("{:#x} <{}>", value, name),
+                None => format!("{:#x}", value),
+            },
+            "rbp" | "fp" | "rsp" | "sp" | "fs_base" | "gs_base" => format!("{:#x}", value),
+            "eflags" => format_eflags(value),
+            _ => (value as i64).to_string(),
+        };
+        format!("{:<19}{}", format!("{:#x}", value), natural)
+    }
+
+    /// Prints the value a function just returned: %xmm0 for floating point types, %rax for
+    /// everything else. Without type information, %rax is shown as a plain integer.
+    fn print_return_value(&self, return_type: Option<Type>) {
+        let infer = self.inferior.as_ref().unwrap();
+        let regs = match infer.getregs() {
+            Ok(regs) => regs,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let return_type = match return_type {
+            Some(return_type) => return_type,
+            None => {
+                println!("Value returned: {} ({:#x})", regs.rax as i64, regs.rax);
+                return;
+            }
+        };
+        let bytes = if return_type.strip().kind == TypeKind::Base(Encoding::Float) {
+            match infer.getfpregs() {
+                Ok(fpregs) => fpregs.xmm_space[..4]
+                    .iter()
+                    .flat_map(|word| word.to_le_bytes().to_vec())
+                    .collect(),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            }
+        } else {
+            regs.rax.to_le_bytes().to_vec()
+        };
+        let size = return_type.size.min(bytes.len());
+        let read_memory = |addr, len| infer.read_memory(addr, len, &self.breakpoints_map).ok();
+        println!(
+            "Value returned: {}",
+            self.dwarf_data.format_value(&return_type, &bytes[..size], &read_memory)
+        );
+    }
+
+    /// Prints the source location of an address, or without debug info, the address and the
+    /// function symbol it is in
+    fn print_location(&self, rip: usize) {
+        let dwarf_data = self.module(rip);
+        match dwarf_data.get_line_from_addr(rip) {
+            Some(line) => println!("Stopped at {}:{}", line.file, line.number),
+            None => match dwarf_data.get_symbol_from_addr(rip) {
+                Some(symbol) => println!("Stopped at {:#x} in {} ()", rip, symbol),
+                None => println!("Stopped at {:#x}", rip),
+            },
+        }
+    }
+}
+
+/// Announces that a watchpoint on a local was deleted because its frame returned
+fn left_scope(number: usize) -> String {
+    format!(
+        "\nWatchpoint {} deleted because the program has left the block in\nwhich its expression is valid.",
+        number
+    )
+}
+
+/// Returns the debug information of the shared library mapped at `addr`, or the executable's
+fn find_module<'a>(dwarf_data: &'a DwarfData, libraries: &'a [SharedLibrary], addr: usize) -> &'a DwarfData {
+    libraries
+        .iter()
+        .map(|library| &library.dwarf_data)
+        .find(|library| library.contains_addr(addr))
+        .unwrap_or(dwarf_data)
+}
+
+/// Finds a function by its debug information, or else by its symbol
+fn function_address(dwarf_data: &DwarfData, name: &str) -> Option<usize> {
+    dwarf_data
+        .get_addr_for_function(None, name)
+        .or_else(|| dwarf_data.get_symbol(name).map(|(address, _)| address))
+}
+
+/// Parses a signal given by its name, with or without the SIG prefix and in any case, or by
+/// its number
+fn parse_signal(text: &str) -> Option<Signal> {
+    if let Ok(number) = text.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = text.to_uppercase();
+    if name.starts_with("SIG") {
+        name.parse().ok()
+    } else {
+        format!("SIG{}", name).parse().ok()
+    }
+}
+
+/// Splits `<target> = <value>` at its assignment operator, leaving `==` alone
+fn split_assignment(text: &str) -> Option<(&str, &str)> {
+    let bytes = text.as_bytes();
+    let pos = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+    })?;
+    let (target, value) = (text[..pos].trim(), text[pos + 1..].trim());
+    if target.is_empty() || value.is_empty() {
+        None
+    } else {
+        Some((target, value))
+    }
+}
+
+/// Formats the x87 and SSE registers for `info all-registers`: the stack registers with their
+/// value and raw bits, the control and status words, and the vector registers as each of the
+/// lane types they may hold
+fn fp_register_rows(fpregs: &libc::user_fpregs_struct) -> Vec<(String, String)> {
+    let words_to_bytes =
+        |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect() };
+    let mut rows = Vec::new();
+    for i in 0..8 {
+        let bytes = words_to_bytes(&fpregs.st_space[i * 4..i * 4 + 4]);
+        let raw: Vec<String> = bytes[..10].iter().rev().map(|byte| format!("{:02x}", byte)).collect();
+        let value = format_float(read_float(&bytes[..10]));
+        rows.push((format!("st{}", i), format!("{:<19}(raw 0x{})", value, raw.concat())));
+    }
+    let control = [
+        ("fctrl", fpregs.cwd as u64),
+        ("fstat", fpregs.swd as u64),
+        ("ftag", fpregs.ftw as u64),
+        ("fop", fpregs.fop as u64),
+        ("fioff", fpregs.rip),
+        ("fooff", fpregs.rdp),
+    ];
+    for (name, value) in control.iter() {
+        rows.push((name.to_string(), format!("{:<19}{}", format!("{:#x}", value), value)));
+    }
+    let mxcsr = fpregs.mxcsr as u64;
+    rows.push(("mxcsr".to_string(), format!("{:<19}{}", format!("{:#x}", mxcsr), format_mxcsr(mxcsr))));
+    for i in 0..16 {
+        let bytes = words_to_bytes(&fpregs.xmm_space[i * 4..i * 4 + 4]);
+        let floats: Vec<String> = bytes
+            .chunks(4)
+            .map(|lane| format_float(f32::from_bits(read_unsigned(lane) as u32)))
+            .collect();
+        let doubles: Vec<String> = bytes.chunks(8).map(|lane| format_float(read_float(lane))).collect();
+        let int32s: Vec<String> = bytes.chunks(4).map(|lane| format!("{:#x}", read_unsigned(lane))).collect();
+        let int64s: Vec<String> = bytes.chunks(8).map(|lane| format!("{:#x}", read_unsigned(lane))).collect();
+        let mut uint128 = [0; 16];
+        uint128.copy_from_slice(&bytes);
+        rows.push((
+            format!("xmm{}", i),
+            format!(
+                "{{v4_float = {{{}}}, v2_double = {{{}}}, v4_int32 = {{{}}}, v2_int64 = {{{}}}, uint128 = {:#x}}}",
+                floats.join(", "),
+                doubles.join(", "),
+                int32s.join(", "),
+                int64s.join(", "),
+                u128::from_le_bytes(uint128),
+            ),
+        ));
+    }
+    rows
+}
+
+/// Lists the exception masks and flags set in %mxcsr the way gdb does, e.g. "[ IM DM ]"
+fn format_mxcsr(mxcsr: u64) -> String {
+    const FLAGS: &[(u64, &str)] = &[
+        (0, "IE"),
+        (1, "DE"),
+        (2, "ZE"),
+        (3, "OE"),
+        (4, "UE"),
+        (5, "PE"),
+        (6, "DAZ"),
+        (7, "IM"),
+        (8, "DM"),
+        (9, "ZM"),
+        (10, "OM"),
+        (11, "UM"),
+        (12, "PM"),
+        (15, "FZ"),
+    ];
+    format_flags(mxcsr, FLAGS)
+}
+
+/// Lists the status flags set in %eflags the way gdb does, e.g. "[ ZF PF ]"
+fn format_eflags(eflags: u64) -> String {
+    const FLAGS: &[(u64, &str)] = &[
+        (0, "CF"),
+        (2, "PF"),
+        (4, "AF"),
+        (6, "ZF"),
+        (7, "SF"),
+        (8, "TF"),
+        (9, "IF"),
+        (10, "DF"),
+        (11, "OF"),
+    ];
+    format_flags(eflags, FLAGS)
+}
+
+/// Lists the names of the bits set in `value`
+fn format_flags(value: u64, flags: &[(u64, &str)]) -> String {
+    let set: Vec<&str> = flags
+        .iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
 }
@@ -4,6 +4,35 @@ pub enum DebuggerCommand {
     Continue,
     Backtrace,
     Break(Vec<String>),
+    Tbreak(Vec<String>),
+    Step,
+    Next,
+    Finish,
+    Until(Vec<String>),
+    Advance(Vec<String>),
+    Print(Vec<String>),
+    Info(Vec<String>),
+    Delete(Vec<String>),
+    Disable(Vec<String>),
+    Enable(Vec<String>),
+    Ignore(Vec<String>),
+    Watch(Vec<String>),
+    Rwatch(Vec<String>),
+    Awatch(Vec<String>),
+    Catch(Vec<String>),
+    Trace(Vec<String>),
+    Attach(Vec<String>),
+    Detach,
+    Up(Vec<String>),
+    Down(Vec<String>),
+    Frame(Vec<String>),
+    Disassemble(Vec<String>),
+    Set(Vec<String>),
+    Handle(Vec<String>),
+    Signal(Vec<String>),
+    Thread(Vec<String>),
+    /// `x/<format> <address>`; the format is empty for a plain `x`
+    Examine(String, Vec<String>),
 }
 
 impl DebuggerCommand {
@@ -24,6 +53,86 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "tb" | "tbreak" => Some(DebuggerCommand::Tbreak(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "u" | "until" => Some(DebuggerCommand::Until(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "advance" => Some(DebuggerCommand::Advance(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "p" | "print" => {
+                let args = tokens[1..].to_vec();
+                Some(DebuggerCommand::Print(
+                    args.iter().map(|s| s.to_string()).collect(),
+                ))
+            }
+            "i" | "info" => Some(DebuggerCommand::Info(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "d" | "delete" => Some(DebuggerCommand::Delete(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "disable" => Some(DebuggerCommand::Disable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "enable" => Some(DebuggerCommand::Enable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "wa" | "watch" => Some(DebuggerCommand::Watch(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "rwatch" => Some(DebuggerCommand::Rwatch(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "awatch" => Some(DebuggerCommand::Awatch(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "catch" => Some(DebuggerCommand::Catch(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "trace" => Some(DebuggerCommand::Trace(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "attach" => Some(DebuggerCommand::Attach(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "detach" => Some(DebuggerCommand::Detach),
+            "up" => Some(DebuggerCommand::Up(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "down" => Some(DebuggerCommand::Down(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "f" | "frame" => Some(DebuggerCommand::Frame(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "set" => Some(DebuggerCommand::Set(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "signal" => Some(DebuggerCommand::Signal(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "t" | "thread" => Some(DebuggerCommand::Thread(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "disas" | "disassemble" => Some(DebuggerCommand::Disassemble(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            cmd if cmd == "x" || cmd.starts_with("x/") => Some(DebuggerCommand::Examine(
+                cmd[1..].trim_start_matches('/').to_string(),
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
             // Default case:
             _ => None,
         }
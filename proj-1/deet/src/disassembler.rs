@@ -0,0 +1,52 @@
+//! Decoding of x86-64 machine code into the AT&T syntax that gdb shows
+
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction, OpKind};
+
+/// Longest possible x86 instruction
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+pub struct DecodedInstruction {
+    pub addr: usize,
+    pub len: usize,
+    /// e.g. `mov    %rsp,%rbp`, or `(bad)` for bytes that are not an instruction
+    pub text: String,
+    /// Destination of a direct jump or call
+    pub branch_target: Option<usize>,
+}
+
+/// Decodes the instructions in `bytes`, which were read from `addr`. Decoding stops before an
+/// instruction that may be cut off at the end of `bytes`.
+pub fn decode(bytes: &[u8], addr: usize) -> Vec<DecodedInstruction> {
+    let mut decoder = Decoder::with_ip(64, bytes, addr as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_first_operand_char_index(7);
+    formatter.options_mut().set_uppercase_hex(false);
+    formatter.options_mut().set_branch_leading_zeros(false);
+    let mut instruction = Instruction::default();
+    let mut decoded = Vec::new();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        let offset = instruction.ip() as usize - addr;
+        if instruction.is_invalid() && offset + MAX_INSTRUCTION_LENGTH > bytes.len() {
+            break;
+        }
+        let mut text = String::new();
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        let branch_target = if instruction.op_count() > 0 && instruction.op0_kind() == OpKind::NearBranch64 {
+            Some(instruction.near_branch64() as usize)
+        } else {
+            None
+        };
+        decoded.push(DecodedInstruction {
+            addr: instruction.ip() as usize,
+            len: instruction.len(),
+            text,
+            branch_target,
+        });
+    }
+    decoded
+}
@@ -1,6 +1,7 @@
 use crate::gimli_wrapper;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, ObjectSegment};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
@@ -10,9 +11,24 @@ pub enum Error {
     DwarfFormatError(gimli_wrapper::Error),
 }
 
+/// Arrays longer than this are cut off when printed
+const MAX_ARRAY_ELEMENTS: usize = 200;
+
 pub struct DwarfData {
     files: Vec<File>,
+    types: HashMap<usize, Type>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    call_frame_info: gimli_wrapper::CallFrameInfo,
+    symbols: Vec<Symbol>,
+    /// Difference between where the object is loaded and its link-time addresses. Everything
+    /// in `files` and `symbols` is already relocated by it.
+    load_bias: usize,
+    /// Entry point, relocated
+    entry: usize,
+    /// Lowest and highest address covered by the object's segments, relocated
+    address_range: (usize, usize),
+    /// Address of the .dynamic section, relocated. Static executables have none.
+    dynamic_section: Option<usize>,
 }
 
 impl fmt::Debug for DwarfData {
@@ -38,60 +54,214 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
+        let symbols = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|(_, symbol)| {
+                symbol.kind() == object::SymbolKind::Text || symbol.kind() == object::SymbolKind::Data
+            })
+            .filter_map(|(_, symbol)| {
+                Some(Symbol {
+                    name: symbol.name()?.to_string(),
+                    address: symbol.address() as usize,
+                    size: symbol.size() as usize,
+                    is_function: symbol.kind() == object::SymbolKind::Text,
+                })
+            })
+            .collect();
+        let address_range = object
+            .segments()
+            .filter(|segment| segment.size() > 0)
+            .fold((usize::MAX, 0), |(start, end), segment| {
+                let address = segment.address() as usize;
+                (start.min(address), end.max(address + segment.size() as usize))
+            });
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
+            files,
+            types,
+            call_frame_info: gimli_wrapper::CallFrameInfo::load(&object, endian),
+            symbols,
             addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            load_bias: 0,
+            entry: object.entry() as usize,
+            address_range,
+            dynamic_section: object
+                .section_by_name(".dynamic")
+                .map(|section| section.address() as usize),
         })
     }
 
-    #[allow(dead_code)]
-    fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+    /// Debug information for no code at all that only knows `types`, each at the offset of its
+    /// index, for testing what looks types up
+    #[cfg(test)]
+    pub fn from_types(types: Vec<Type>) -> DwarfData {
+        use addr2line::gimli::{EndianRcSlice, RunTimeEndian};
+        let empty = || EndianRcSlice::new(std::rc::Rc::from(&[][..]), RunTimeEndian::Little);
+        let addr2line = Context::from_sections(
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty().into(),
+            empty(),
+        )
+        .unwrap();
+        DwarfData {
+            files: Vec::new(),
+            types: types.into_iter().enumerate().collect(),
+            addr2line,
+            call_frame_info: gimli_wrapper::CallFrameInfo::empty(),
+            symbols: Vec::new(),
+            load_bias: 0,
+            entry: 0,
+            address_range: (0, 0),
+            dynamic_section: None,
+        }
+    }
+
+    /// Moves every address to where the object is loaded: its link-time address plus
+    /// `load_bias`. Position-independent executables and shared libraries are linked at 0 and
+    /// loaded somewhere else.
+    pub fn relocate(&mut self, load_bias: usize) {
+        let delta = load_bias.wrapping_sub(self.load_bias);
+        let relocate_variable = |var: &mut Variable| match &mut var.location {
+            Location::Address(addr) => *addr = addr.wrapping_add(delta),
+            Location::Expression(_) => {}
+            Location::List(entries) => {
+                for (start, end, _) in entries.iter_mut() {
+                    *start = start.wrapping_add(delta);
+                    *end = end.wrapping_add(delta);
+                }
+            }
+        };
+        for file in self.files.iter_mut() {
+            file.global_variables.iter_mut().for_each(relocate_variable);
+            for func in file.functions.iter_mut() {
+                func.address = func.address.wrapping_add(delta);
+                func.variables.iter_mut().for_each(relocate_variable);
+            }
+            for line in file.lines.iter_mut() {
+                line.address = line.address.wrapping_add(delta);
+            }
+        }
+        for symbol in self.symbols.iter_mut() {
+            symbol.address = symbol.address.wrapping_add(delta);
+        }
+        self.entry = self.entry.wrapping_add(delta);
+        self.address_range = (
+            self.address_range.0.wrapping_add(delta),
+            self.address_range.1.wrapping_add(delta),
+        );
+        self.dynamic_section = self.dynamic_section.map(|addr| addr.wrapping_add(delta));
+        self.load_bias = load_bias;
+    }
+
+    pub fn load_bias(&self) -> usize {
+        self.load_bias
+    }
+
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+
+    pub fn address_range(&self) -> (usize, usize) {
+        self.address_range
+    }
+
+    pub fn dynamic_section(&self) -> Option<usize> {
+        self.dynamic_section
+    }
+
+    /// Returns true if `curr_addr` lies within one of the object's segments
+    pub fn contains_addr(&self, curr_addr: usize) -> bool {
+        self.address_range.0 <= curr_addr && curr_addr < self.address_range.1
+    }
+
+    /// Returns the compilation units called `file`. A relative name also matches the units
+    /// whose path ends with it, so `foo.c` finds `src/foo.c`.
+    pub fn find_files(&self, file: &str) -> Vec<&File> {
+        self.files
+            .iter()
+            .filter(|f| {
+                f.name == file || (!file.starts_with('/') && f.name.ends_with(&format!("/{}", file)))
+            })
+            .collect()
+    }
+
+    /// Returns the compilation unit whose functions contain `curr_addr`
+    pub fn get_file_containing_addr(&self, curr_addr: usize) -> Option<&File> {
+        self.files.iter().find(|file| {
+            file.functions.iter().any(|func| {
+                func.address <= curr_addr && curr_addr < func.address + func.text_length
+            })
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns the functions called `func_name`, each with the compilation unit defining it,
+    /// optionally only those of the units matching `file`. There can be several static
+    /// functions of the same name.
+    pub fn find_functions(&self, file: Option<&str>, func_name: &str) -> Vec<(&File, &Function)> {
+        let files = match file {
+            Some(filename) => self.find_files(filename),
+            None => self.files.iter().collect(),
+        };
+        files
+            .into_iter()
+            .flat_map(|file| {
+                file.functions
+                    .iter()
+                    .filter(|func| func.name == func_name)
+                    .map(move |func| (file, func))
+            })
+            .collect()
+    }
+
+    /// Returns where a breakpoint on a source line goes: the lowest address of the first line
+    /// at or after `line_number` that has code. A line where a function starts resolves to the
+    /// end of its prologue. Without a file, the one defining main is used.
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
-            Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            Some(filename) => *self.find_files(filename).first()?,
+            None => self
+                .find_functions(None, "main")
+                .first()
+                .map(|(file, _)| *file)
+                .or_else(|| self.files.first())?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        let number = target_file
+            .lines
+            .iter()
+            .map(|line| line.number)
+            .filter(|number| *number >= line_number)
+            .min()?;
+        let addr = target_file
+            .lines
+            .iter()
+            .filter(|line| line.number == number)
+            .map(|line| line.address)
+            .min()?;
+        match self.get_function_containing_addr(addr) {
+            Some(func) if func.address == addr => Some(self.get_addr_after_prologue(func)),
+            _ => Some(addr),
+        }
     }
 
-    #[allow(dead_code)]
+    /// Returns where a breakpoint on a function goes: right after its prologue, once the
+    /// arguments are in place
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
-        match file {
-            Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
-                    .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
-            ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
-                    }
-                }
-                None
-            }
-        }
+        let (_, func) = *self.find_functions(file, func_name).first()?;
+        Some(self.get_addr_after_prologue(func))
     }
 
-    #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(self.load_bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -100,17 +270,298 @@ impl DwarfData {
         })
     }
 
-    #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(curr_addr.wrapping_sub(self.load_bias).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the name of the function symbol covering `curr_addr`. Unlike the other lookups,
+    /// this also works for code without debug information.
+    pub fn get_symbol_from_addr(&self, curr_addr: usize) -> Option<&str> {
+        self.get_symbol_containing_addr(curr_addr).map(|(name, _, _)| name)
+    }
+
+    /// Returns the name, address and size of the function symbol covering `curr_addr`
+    pub fn get_symbol_containing_addr(&self, curr_addr: usize) -> Option<(&str, usize, usize)> {
+        self.find_symbol_containing_addr(curr_addr, true)
+    }
+
+    /// Returns the name, address and size of the data symbol covering `curr_addr`, e.g. a
+    /// global variable
+    pub fn get_object_containing_addr(&self, curr_addr: usize) -> Option<(&str, usize, usize)> {
+        self.find_symbol_containing_addr(curr_addr, false)
+    }
+
+    fn find_symbol_containing_addr(&self, curr_addr: usize, is_function: bool) -> Option<(&str, usize, usize)> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.is_function == is_function)
+            .find(|symbol| {
+                symbol.address <= curr_addr
+                    && (curr_addr < symbol.address + symbol.size || curr_addr == symbol.address)
+            })
+            .map(|symbol| (symbol.name.as_str(), symbol.address, symbol.size))
+    }
+
+    /// Returns the address and size of the function symbol called `name`
+    pub fn get_symbol(&self, name: &str) -> Option<(usize, usize)> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.is_function && symbol.name == name && symbol.address != 0)
+            .map(|symbol| (symbol.address, symbol.size))
+    }
+
+    /// Returns the call frame information rule for the code at `curr_addr`
+    pub fn get_frame_rule(&self, curr_addr: usize) -> Option<FrameRule> {
+        self.call_frame_info
+            .frame_rule(curr_addr.wrapping_sub(self.load_bias) as u64)
+    }
+
+    /// Returns the function whose code contains `curr_addr`
+    pub fn get_function_containing_addr(&self, curr_addr: usize) -> Option<&Function> {
+        self.files.iter().flat_map(|file| file.functions.iter()).find(|func| {
+            func.address <= curr_addr && curr_addr < func.address + func.text_length
+        })
+    }
+
+    /// Looks up a variable visible at `curr_addr`: locals and parameters of the enclosing
+    /// function first, then globals of the same compilation unit, then any other global.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        if let Some(func) = self.get_function_containing_addr(curr_addr) {
+            if let Some(var) = func.variables.iter().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        let current_file = self.files.iter().find(|file| {
+            file.functions.iter().any(|func| {
+                func.address <= curr_addr && curr_addr < func.address + func.text_length
+            })
+        });
+        current_file
+            .into_iter()
+            .chain(self.files.iter())
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Works out where a variable is when the frame described by `context` is at `pc`
+    pub fn locate_variable(
+        &self,
+        var: &Variable,
+        pc: usize,
+        context: &dyn FrameContext,
+    ) -> Result<Place, String> {
+        let expression = match &var.location {
+            Location::Address(addr) => return Ok(Place::Memory(*addr)),
+            Location::Expression(expression) => expression,
+            Location::List(entries) => {
+                match entries.iter().find(|(start, end, _)| *start <= pc && pc < *end) {
+                    Some((_, _, expression)) => expression,
+                    None => return Ok(Place::OptimizedOut),
+                }
+            }
+        };
+        let frame_base = self
+            .get_function_containing_addr(pc)
+            .and_then(|func| func.frame_base.as_ref());
+        gimli_wrapper::evaluate_location(
+            expression,
+            frame_base,
+            var.entity_type.size,
+            self.load_bias,
+            context,
+        )
+    }
+
+    /// Returns the address of the first line after the function's prologue, i.e. the second
+    /// line-table row inside the function. Falls back to the function's entry address.
+    pub fn get_addr_after_prologue(&self, func: &Function) -> usize {
+        let end = func.address + func.text_length;
+        self.files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .filter(|line| func.address < line.address && line.address < end)
+            .map(|line| line.address)
+            .min()
+            .unwrap_or(func.address)
+    }
+
+    /// Returns true if `curr_addr` is the first instruction of some line-table row
+    pub fn is_line_start(&self, curr_addr: usize) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.lines.iter().any(|line| line.address == curr_addr))
+    }
+
+    /// Returns the type whose DIE is at `offset` in .debug_info
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        self.types.get(&offset)
+    }
+
+    /// Returns the offset of the type with the given name, e.g. "long unsigned int" or
+    /// "struct node". A definition wins over a mere declaration of the same struct.
+    pub fn find_type_offset(&self, name: &str) -> Option<usize> {
+        self.types
+            .iter()
+            .filter(|(_, entity_type)| entity_type.name == name)
+            .max_by_key(|(offset, entity_type)| (entity_type.size, std::cmp::Reverse(**offset)))
+            .map(|(offset, _)| *offset)
+    }
+
+    /// Returns the type with the given name
+    pub fn find_type(&self, name: &str) -> Option<&Type> {
+        self.get_type(self.find_type_offset(name)?)
+    }
+
+    /// Returns the value of the enumeration constant called `name`, and its enum type
+    pub fn find_enumerator(&self, name: &str) -> Option<(i64, &Type)> {
+        let mut enums: Vec<(&usize, &Type)> = self.types.iter().collect();
+        enums.sort_by_key(|(offset, _)| **offset);
+        enums.into_iter().find_map(|(_, entity_type)| match &entity_type.kind {
+            TypeKind::Enum(enumerators) => enumerators
+                .iter()
+                .find(|(enumerator, _)| enumerator == name)
+                .map(|(_, value)| (*value, entity_type)),
+            _ => None,
+        })
+    }
+
+    /// Returns the offset of a pointer type pointing to the type at `target`
+    pub fn find_pointer_type(&self, target: Option<usize>) -> Option<usize> {
+        self.types
+            .iter()
+            .filter(|(_, entity_type)| entity_type.kind == TypeKind::Pointer(target))
+            .map(|(offset, _)| *offset)
+            .min()
+    }
+
+    /// Returns the type a pointer of the given kind points to
+    pub fn get_pointee_type(&self, target: Option<usize>) -> Type {
+        match target {
+            Some(offset) => self
+                .get_type(offset)
+                .cloned()
+                .unwrap_or_else(|| Type::new("<unknown>".to_string(), 0, TypeKind::Unknown)),
+            None => Type::new("void".to_string(), 0, TypeKind::Void),
+        }
+    }
+
+    /// Formats the raw bytes of a value the way C would print it. `read_memory(addr, len)` is
+    /// used to show the strings that char pointers point to.
+    pub fn format_value(
+        &self,
+        entity_type: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+    ) -> String {
+        self.format_value_inner(entity_type, bytes, read_memory, true)
+    }
+
+    /// Formats a pointer of the type called `type_name` that points to a `pointee`. Function
+    /// pointers show the function, and char pointers the string.
+    pub fn format_pointer(
+        &self,
+        type_name: &str,
+        pointee: &Type,
+        addr: usize,
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        top_level: bool,
+    ) -> String {
+        if pointee.strip().kind == TypeKind::Function {
+            match self.get_function_from_addr(addr) {
+                Some(func) => format!("({}) {:#x} <{}>", type_name, addr, func),
+                None => format!("({}) {:#x}", type_name, addr),
+            }
+        } else if pointee.is_char() && addr != 0 {
+            match read_c_string(addr, read_memory) {
+                Some(string) => format!("{:#x} {}", addr, format_string(&string)),
+                None => format!("{:#x} <error: Cannot access memory>", addr),
+            }
+        } else if top_level {
+            format!("({}) {:#x}", type_name, addr)
+        } else {
+            format!("{:#x}", addr)
+        }
+    }
+
+    fn format_value_inner(
+        &self,
+        entity_type: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        top_level: bool,
+    ) -> String {
+        match &entity_type.kind {
+            TypeKind::Base(encoding) => format_base_value(*encoding, bytes),
+            TypeKind::Pointer(target) => {
+                let addr = read_unsigned(bytes) as usize;
+                let pointee = self.get_pointee_type(*target);
+                self.format_pointer(&entity_type.name, &pointee, addr, read_memory, top_level)
+            }
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = match (member.bit_size, member.bit_offset) {
+                            (Some(bit_size), bit_offset) => format_bit_field(
+                                &member.entity_type,
+                                bytes,
+                                bit_offset.unwrap_or(member.offset * 8),
+                                bit_size,
+                            ),
+                            _ => {
+                                let end = member.offset + member.entity_type.size;
+                                let field_bytes = bytes.get(member.offset..end).unwrap_or(&[]);
+                                self.format_value_inner(
+                                    &member.entity_type,
+                                    field_bytes,
+                                    read_memory,
+                                    false,
+                                )
+                            }
+                        };
+                        format!("{} = {}", member.name, value)
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array(element_type, count) => {
+                let count = count.unwrap_or(0);
+                if element_type.is_char() {
+                    return format_string(&bytes[..count.min(bytes.len())]);
+                }
+                let size = element_type.size.max(1);
+                let mut elements: Vec<String> = bytes
+                    .chunks(size)
+                    .take(count.min(MAX_ARRAY_ELEMENTS))
+                    .map(|chunk| self.format_value_inner(element_type, chunk, read_memory, false))
+                    .collect();
+                if count > MAX_ARRAY_ELEMENTS {
+                    elements.push("...".to_string());
+                }
+                format!("{{{}}}", elements.join(", "))
+            }
+            TypeKind::Enum(enumerators) => {
+                let value = read_signed(bytes);
+                match enumerators.iter().find(|(_, enum_value)| *enum_value == value) {
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => {
+                self.format_value_inner(target, bytes, read_memory, top_level)
+            }
+            TypeKind::Void => "void".to_string(),
+            TypeKind::Function => "<function>".to_string(),
+            TypeKind::Unknown => format_raw_bytes(bytes),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -148,32 +599,298 @@ impl DwarfData {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
-        Type {
-            name: name,
-            size: size,
+    pub fn new(name: String, size: usize, kind: TypeKind) -> Self {
+        Type { name, size, kind }
+    }
+
+    /// Looks through typedefs and const/volatile qualifiers
+    pub fn strip(&self) -> &Type {
+        match &self.kind {
+            TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => target.strip(),
+            _ => self,
+        }
+    }
+
+    /// Returns true for char, signed char and unsigned char
+    pub fn is_char(&self) -> bool {
+        matches!(self.strip().kind, TypeKind::Base(Encoding::SignedChar) | TypeKind::Base(Encoding::UnsignedChar))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Void,
+    Base(Encoding),
+    /// Offset of the pointed-to type (see DwarfData::get_type), or None for void *
+    Pointer(Option<usize>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    /// Element type and element count. The count is None for arrays of unknown size, like
+    /// `extern int a[];`
+    Array(Box<Type>, Option<usize>),
+    Enum(Vec<(String, i64)>),
+    Typedef(Box<Type>),
+    Qualified(Qualifier, Box<Type>),
+    Function,
+    Unknown,
+}
+
+// Deriving this needs `#[default]`, which the compiler in the Dockerfile does not have yet
+#[allow(clippy::derivable_impls)]
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Unknown
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Qualifier {
+    Const,
+    Volatile,
+    Restrict,
+}
+
+/// A field of a struct or union
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: String,
+    pub entity_type: Type,
+    pub offset: usize, // Byte offset from the start of the struct
+    pub bit_size: Option<usize>,
+    pub bit_offset: Option<usize>, // Bit offset from the start of the struct, for bit fields
+}
+
+/// How the bits of a base type are interpreted (DW_AT_encoding)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Float,
+    Boolean,
+}
+
+/// Returns the one of `modules` that is mapped at `addr`. Addresses outside all of them are
+/// looked up in the first one, which is the executable.
+pub fn module_for_addr<'a>(modules: &[&'a DwarfData], addr: usize) -> &'a DwarfData {
+    modules
+        .iter()
+        .find(|module| module.contains_addr(addr))
+        .unwrap_or(&modules[0])
+}
+
+/// Reads a little-endian unsigned integer of up to 8 bytes
+pub fn read_unsigned(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .enumerate()
+        .fold(0, |acc, (i, byte)| acc | ((*byte as u64) << (8 * i)))
+}
+
+/// Reads a little-endian two's complement integer of up to 8 bytes
+pub fn read_signed(bytes: &[u8]) -> i64 {
+    let bits = 8 * bytes.len().min(8) as u32;
+    let value = read_unsigned(bytes);
+    if bits == 0 || bits == 64 {
+        value as i64
+    } else {
+        ((value << (64 - bits)) as i64) >> (64 - bits)
+    }
+}
+
+/// Converts an x87 80-bit extended precision float (C's long double) to an f64
+fn read_extended_float(bytes: &[u8]) -> f64 {
+    let mantissa = read_unsigned(&bytes[..8]);
+    let sign_exponent = read_unsigned(&bytes[8..10]) as i32;
+    let exponent = sign_exponent & 0x7fff;
+    let value = if exponent == 0x7fff {
+        if mantissa << 1 == 0 {
+            f64::INFINITY
+        } else {
+            f64::NAN
+        }
+    } else {
+        mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+    };
+    if sign_exponent & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Reads a float, double or long double of the given byte width as an f64
+pub fn read_float(bytes: &[u8]) -> f64 {
+    match bytes.len() {
+        4 => f32::from_bits(read_unsigned(bytes) as u32) as f64,
+        8 => f64::from_bits(read_unsigned(bytes)),
+        _ => read_extended_float(bytes),
+    }
+}
+
+/// Escapes a byte for use inside a C character or string literal delimited by `quote`
+pub fn escape_byte(value: u8, quote: u8) -> String {
+    match value {
+        0 => "\\0".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        _ if value == quote => format!("\\{}", value as char),
+        0x20..=0x7e => (value as char).to_string(),
+        _ => format!("\\{:03o}", value),
+    }
+}
+
+pub fn format_char(value: u8) -> String {
+    format!("'{}'", escape_byte(value, b'\''))
+}
+
+/// Formats bytes as a C string literal, stopping at the first NUL
+pub fn format_string(bytes: &[u8]) -> String {
+    let escaped: Vec<String> = bytes
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| escape_byte(*byte, b'"'))
+        .collect();
+    format!("\"{}\"", escaped.concat())
+}
+
+/// Prints very large and very small magnitudes in scientific notation, like %g does
+pub fn format_float<T: Copy + Into<f64> + fmt::Display + fmt::LowerExp>(value: T) -> String {
+    let magnitude = value.into().abs();
+    if magnitude != 0.0 && !(1e-4..1e16).contains(&magnitude) {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_base_value(encoding: Encoding, bytes: &[u8]) -> String {
+    match encoding {
+        Encoding::Signed => read_signed(bytes).to_string(),
+        Encoding::Unsigned => read_unsigned(bytes).to_string(),
+        Encoding::SignedChar => format!("{} {}", read_signed(bytes), format_char(bytes[0])),
+        Encoding::UnsignedChar => format!("{} {}", read_unsigned(bytes), format_char(bytes[0])),
+        Encoding::Boolean => match read_unsigned(bytes) {
+            0 => "false".to_string(),
+            1 => "true".to_string(),
+            other => other.to_string(),
+        },
+        Encoding::Float => match bytes.len() {
+            4 => format_float(f32::from_bits(read_unsigned(bytes) as u32)),
+            8 => format_float(f64::from_bits(read_unsigned(bytes))),
+            10 | 16 => format_float(read_extended_float(bytes)),
+            _ => format_raw_bytes(bytes),
+        },
+    }
+}
+
+/// Reads a NUL-terminated string of at most MAX_ARRAY_ELEMENTS bytes
+fn read_c_string(addr: usize, read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>) -> Option<Vec<u8>> {
+    let mut string = Vec::new();
+    while string.len() < MAX_ARRAY_ELEMENTS {
+        // Read one byte at a time past the first chunk in case the string ends right before
+        // an unmapped page
+        let chunk_len = if string.is_empty() { 8 - addr % 8 } else { 1 };
+        let chunk = match read_memory(addr + string.len(), chunk_len) {
+            Some(chunk) => chunk,
+            None if string.is_empty() => return None,
+            None => break,
+        };
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                string.extend_from_slice(&chunk[..end]);
+                return Some(string);
+            }
+            None => string.extend_from_slice(&chunk),
+        }
+    }
+    Some(string)
+}
+
+fn format_bit_field(entity_type: &Type, bytes: &[u8], bit_offset: usize, bit_size: usize) -> String {
+    let first = bit_offset / 8;
+    // Bit fields are at least one bit wide
+    let last = (bit_offset + bit_size - 1) / 8 + 1;
+    let raw = read_unsigned(bytes.get(first..last).unwrap_or(&[])) >> (bit_offset % 8);
+    let mask = if bit_size >= 64 { !0 } else { (1u64 << bit_size) - 1 };
+    let value = raw & mask;
+    match entity_type.strip().kind {
+        TypeKind::Base(Encoding::Signed) if bit_size < 64 && value >> (bit_size - 1) & 1 == 1 => {
+            ((value | !mask) as i64).to_string()
         }
+        _ => value.to_string(),
     }
 }
 
+fn format_raw_bytes(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+    format!("{{{}}}", hex.join(", "))
+}
+
+/// An ELF symbol table entry for a function or a data object
+struct Symbol {
+    name: String,
+    address: usize,
+    size: usize,
+    is_function: bool,
+}
+
+/// Where the caller's value of a register can be found, in terms of the canonical frame address
+#[derive(Debug, Clone, Copy)]
+pub enum SavedRegister {
+    Undefined,
+    SameValue,
+    /// Saved in memory at CFA + offset
+    AtCfaOffset(i64),
+    /// The value is CFA + offset
+    CfaOffset(i64),
+    InRegister(u16),
+}
+
+/// How to unwind one frame: the CFA is `cfa_register` + `cfa_offset`, and the caller's
+/// registers are recovered as listed. Registers are DWARF register numbers, and register 16
+/// holds the return address.
+#[derive(Debug, Clone)]
+pub struct FrameRule {
+    pub cfa_register: u16,
+    pub cfa_offset: i64,
+    pub registers: Vec<(u16, SavedRegister)>,
+}
+
+/// Where a variable is kept. A location list gives a different expression for each range of
+/// code, and says nothing about code outside those ranges, where the variable is optimized out.
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
-    FramePointerOffset(isize),
+    Expression(Expression),
+    List(Vec<(usize, usize, Expression)>),
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
-            Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(expression) => write!(f, "Expression({})", expression),
+            Location::List(entries) => {
+                write!(f, "List(")?;
+                for (i, (start, end, expression)) in entries.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}[{:#x}, {:#x}): {}", separator, start, end, expression)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -184,10 +901,51 @@ impl fmt::Debug for Location {
     }
 }
 
+/// A DWARF expression, kept as bytecode and evaluated when the variable is looked at
+#[derive(Clone)]
+pub struct Expression {
+    pub bytecode: Vec<u8>,
+    pub encoding: gimli::Encoding,
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", gimli_wrapper::format_expression(self))
+    }
+}
+
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Where a variable's value is at some point of the program, once its location is evaluated
+#[derive(Debug, Clone)]
+pub enum Place {
+    Memory(usize),
+    /// The value is not in memory, but in registers or computed by the expression
+    Value(Vec<u8>),
+    OptimizedOut,
+}
+
+/// What evaluating a location needs to know about the frame the variable lives in
+pub trait FrameContext {
+    /// Returns the value of a DWARF register, or None if it is not known in this frame
+    fn register(&self, register: u16) -> Option<u64>;
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>>;
+
+    /// The canonical frame address, for DW_OP_call_frame_cfa
+    fn call_frame_cfa(&self) -> Option<usize>;
+}
+
 // For variables and formal parameters
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
+    /// True for a function's formal parameters, false for its locals and for globals
+    pub is_parameter: bool,
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
@@ -200,6 +958,9 @@ pub struct Function {
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    pub return_type: Option<Type>,
+    /// DW_AT_frame_base, which DW_OP_fbreg offsets are relative to
+    pub frame_base: Option<Expression>,
 }
 
 #[derive(Debug, Default, Clone)]
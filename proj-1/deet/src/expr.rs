@@ -0,0 +1,1215 @@
+//! Parsing and evaluation of the C-like expressions accepted by `print` and by breakpoint
+//! conditions, e.g. `*head`, `s.corners[1].x`, `i >= 3 && $rax != 0`.
+
+use crate::dwarf_data::{
+    read_float, read_signed, read_unsigned, DwarfData, Encoding, FrameContext, Member, Place, Type, TypeKind,
+};
+use crate::inferior::{dwarf_register, register_value, Breakpoint, Frame, ProcessState};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A literal with its C type, e.g. int for `1`, unsigned long for `1ul` and char for `'a'`
+    Integer(i64, Type),
+    /// A literal with its C type, double or float
+    Float(f64, Type),
+    Register(String),
+    Variable(String),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Cast(TypeName, Box<Expr>),
+}
+
+/// A type as written in a cast, e.g. `(unsigned long)` or `(struct node **)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeName {
+    /// The name the debug information uses, e.g. "long unsigned int" or "struct node"
+    pub base: String,
+    /// Number of `*`s
+    pub pointers: usize,
+}
+
+/// Words that can only be part of a type name
+const TYPE_KEYWORDS: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool",
+    "struct", "union", "enum", "const", "volatile",
+];
+
+/// Spells a base type the way gcc names it in the debug information, e.g. `unsigned long` as
+/// "long unsigned int"
+fn canonical_base_type(words: &[String]) -> String {
+    let has = |keyword: &str| words.iter().any(|word| word == keyword);
+    let longs = words.iter().filter(|word| *word == "long").count();
+    let unsigned = has("unsigned");
+    if has("void") {
+        "void".to_string()
+    } else if has("_Bool") {
+        "_Bool".to_string()
+    } else if has("float") {
+        "float".to_string()
+    } else if has("double") {
+        if longs > 0 {
+            "long double".to_string()
+        } else {
+            "double".to_string()
+        }
+    } else if has("char") {
+        if unsigned {
+            "unsigned char".to_string()
+        } else if has("signed") {
+            "signed char".to_string()
+        } else {
+            "char".to_string()
+        }
+    } else {
+        let size = if has("short") {
+            "short "
+        } else if longs == 1 {
+            "long "
+        } else if longs > 1 {
+            "long long "
+        } else {
+            ""
+        };
+        format!("{}{}int", size, if unsigned { "unsigned " } else { "" })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    Deref,
+    AddressOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// Maps an operator token to the operator and its C precedence (higher binds tighter)
+    fn from_token(token: &str) -> Option<(BinaryOp, u8)> {
+        Some(match token {
+            "*" => (BinaryOp::Mul, 10),
+            "/" => (BinaryOp::Div, 10),
+            "%" => (BinaryOp::Rem, 10),
+            "+" => (BinaryOp::Add, 9),
+            "-" => (BinaryOp::Sub, 9),
+            "<<" => (BinaryOp::Shl, 8),
+            ">>" => (BinaryOp::Shr, 8),
+            "<" => (BinaryOp::Lt, 7),
+            "<=" => (BinaryOp::Le, 7),
+            ">" => (BinaryOp::Gt, 7),
+            ">=" => (BinaryOp::Ge, 7),
+            "==" => (BinaryOp::Eq, 6),
+            "!=" => (BinaryOp::Ne, 6),
+            "&" => (BinaryOp::BitAnd, 5),
+            "^" => (BinaryOp::BitXor, 4),
+            "|" => (BinaryOp::BitOr, 3),
+            "&&" => (BinaryOp::And, 2),
+            "||" => (BinaryOp::Or, 1),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Integer(i64, Type),
+    Float(f64, Type),
+    Ident(String),
+    Register(String),
+    Punct(&'static str),
+}
+
+/// Longer operators come first so that "->" is not lexed as "-" followed by ">"
+const PUNCTUATION: &[&str] = &[
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "^", "|", "!", "~", "(", ")", "[", "]", ".",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(parse_number(&chars[start..i].iter().collect::<String>())?);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if c == '$' {
+                tokens.push(Token::Register(word[1..].to_string()));
+            } else {
+                tokens.push(Token::Ident(word));
+            }
+        } else if c == '\'' {
+            let (value, len) = parse_char_literal(&chars[i..])?;
+            tokens.push(Token::Integer(value, base_type("char", 1, Encoding::SignedChar)));
+            i += len;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            match PUNCTUATION.iter().find(|punct| rest.starts_with(*punct)) {
+                Some(punct) => {
+                    tokens.push(Token::Punct(punct));
+                    i += punct.len();
+                }
+                None => return Err(format!("Invalid character '{}' in expression.", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Result<Token, String> {
+    let invalid = || format!("Invalid number \"{}\".", text);
+    if text.contains('.') {
+        let digits = text.trim_end_matches(&['f', 'F'][..]);
+        let value = digits.parse::<f64>().map_err(|_| invalid())?;
+        return Ok(if digits.len() < text.len() {
+            Token::Float(value as f32 as f64, base_type("float", 4, Encoding::Float))
+        } else {
+            Token::Float(value, double_type())
+        });
+    }
+    let digits = text.trim_end_matches(&['u', 'U', 'l', 'L'][..]);
+    let suffix = text[digits.len()..].to_ascii_lowercase();
+    let decimal = !digits.starts_with('0') || digits == "0";
+    let parsed = if digits.starts_with("0x") || digits.starts_with("0X") {
+        u64::from_str_radix(&digits[2..], 16)
+    } else if !decimal {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<u64>()
+    };
+    let value = parsed.map_err(|_| invalid())?;
+    // The first type that can hold the value, as in C. Octal and hexadecimal literals may be
+    // unsigned without a suffix.
+    let unsigned = suffix.contains('u');
+    let long = suffix.contains('l');
+    let (size, signed) = if !unsigned && !long && value <= i32::MAX as u64 {
+        (4, true)
+    } else if !long && value <= u32::MAX as u64 && (unsigned || !decimal) {
+        (4, false)
+    } else if !unsigned && value <= i64::MAX as u64 {
+        (8, true)
+    } else {
+        (8, false)
+    };
+    Ok(Token::Integer(value as i64, integer_type(size, signed)))
+}
+
+/// Parses a character literal such as 'a' or '\n'. Returns its value and length in chars.
+fn parse_char_literal(chars: &[char]) -> Result<(i64, usize), String> {
+    let unterminated = || "Unmatched single quote.".to_string();
+    let (value, len) = match chars.get(1) {
+        Some('\\') => {
+            let value = match chars.get(2).ok_or_else(unterminated)? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                other => *other,
+            };
+            (value, 4)
+        }
+        Some(value) => (*value, 3),
+        None => return Err(unterminated()),
+    };
+    if chars.get(len - 1) != Some(&'\'') {
+        return Err(unterminated());
+    }
+    Ok((value as i64, len))
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Tells whether a name is a typedef, struct, union or enum rather than a variable
+    is_type: &'a dyn Fn(&str) -> bool,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(punct)) => Some(punct),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.peek_punct() == Some(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' in expression.", punct))
+        }
+    }
+
+    /// Precedence climbing over the binary operators
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.parse_unary()?;
+        while let Some((op, precedence)) = self.peek_punct().and_then(BinaryOp::from_token) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if let Some((type_name, len)) = self.peek_cast() {
+            self.pos += len;
+            return Ok(Expr::Cast(type_name, Box::new(self.parse_unary()?)));
+        }
+        let op = match self.peek_punct() {
+            Some("-") => UnaryOp::Neg,
+            Some("!") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            Some("*") => UnaryOp::Deref,
+            Some("&") => UnaryOp::AddressOf,
+            Some("+") => {
+                self.pos += 1;
+                return self.parse_unary();
+            }
+            _ => return self.parse_postfix(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    /// Recognizes a cast at the current position and returns the type and the number of tokens
+    /// it takes up. `(name)` is a cast if `name` is a type, as in `(uint32_t)-1`. A name that is
+    /// not known either way is taken for a type when an operand follows, as in `(uint32_t)x`,
+    /// since `(x) y` would not parse otherwise.
+    fn peek_cast(&self) -> Option<(TypeName, usize)> {
+        if self.peek_punct() != Some("(") {
+            return None;
+        }
+        let mut words = Vec::new();
+        let mut pos = self.pos + 1;
+        while let Some(Token::Ident(word)) = self.tokens.get(pos) {
+            words.push(word.clone());
+            pos += 1;
+        }
+        let mut pointers = 0;
+        while self.tokens.get(pos) == Some(&Token::Punct("*")) {
+            pointers += 1;
+            pos += 1;
+        }
+        if words.is_empty() || self.tokens.get(pos) != Some(&Token::Punct(")")) {
+            return None;
+        }
+        pos += 1;
+        words.retain(|word| word != "const" && word != "volatile");
+        let is_keyword = |word: &String| TYPE_KEYWORDS.contains(&word.as_str());
+        let base = match words.as_slice() {
+            [tag, name] if tag == "struct" || tag == "union" || tag == "enum" => format!("{} {}", tag, name),
+            _ if !words.is_empty() && words.iter().all(is_keyword) => canonical_base_type(&words),
+            [name] => {
+                let operand_follows = match self.tokens.get(pos) {
+                    Some(Token::Punct(punct)) => *punct == "(",
+                    Some(_) => true,
+                    None => false,
+                };
+                if pointers == 0 && !operand_follows && !(self.is_type)(name) {
+                    return None;
+                }
+                name.clone()
+            }
+            _ => return None,
+        };
+        Some((TypeName { base, pointers }, pos - self.pos))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek_punct() {
+                Some(".") | Some("->") => {
+                    let arrow = self.peek_punct() == Some("->");
+                    self.pos += 1;
+                    let field = match self.peek() {
+                        Some(Token::Ident(field)) => field.clone(),
+                        _ => return Err("Expected a field name in expression.".to_string()),
+                    };
+                    self.pos += 1;
+                    if arrow {
+                        expr = Expr::Unary(UnaryOp::Deref, Box::new(expr));
+                    }
+                    expr = Expr::Member(Box::new(expr), field);
+                }
+                Some("[") => {
+                    self.pos += 1;
+                    let index = self.parse_binary(0)?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| "A syntax error in expression, near `'.".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Integer(value, entity_type) => Ok(Expr::Integer(value, entity_type)),
+            Token::Float(value, entity_type) => Ok(Expr::Float(value, entity_type)),
+            Token::Ident(name) => Ok(Expr::Variable(name)),
+            Token::Register(name) => Ok(Expr::Register(name)),
+            Token::Punct("(") => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct(punct) => Err(format!("A syntax error in expression, near `{}'.", punct)),
+        }
+    }
+}
+
+/// Parses a complete expression. `is_type` tells the names of types from those of variables,
+/// which C cannot parse without.
+pub fn parse(input: &str, is_type: &dyn Fn(&str) -> bool) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        is_type,
+    };
+    let expr = parser.parse_binary(0)?;
+    if parser.pos != parser.tokens.len() {
+        return Err("A syntax error in expression.".to_string());
+    }
+    Ok(expr)
+}
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// An object in the inferior's memory, with its address and type
+    Lvalue(usize, Type),
+    /// A pointer that is not stored anywhere, such as the result of `&` or of a cast, with the
+    /// type it points to
+    Pointer(usize, Type),
+    /// An integer, already truncated to its type. Chars and enums keep their type so that they
+    /// print as characters and names.
+    Integer(i64, Type),
+    Float(f64, Type),
+    /// An object that is not in memory, such as a variable the compiler keeps in registers, with
+    /// its contents
+    Bytes(Vec<u8>, Type),
+    /// A variable the compiler did not keep at this point of the program
+    OptimizedOut,
+}
+
+/// A base type that does not come from the debug information, for literals and the results of
+/// arithmetic
+fn base_type(name: &str, size: usize, encoding: Encoding) -> Type {
+    Type::new(name.to_string(), size, TypeKind::Base(encoding))
+}
+
+/// The type C arithmetic on integers of the given size and signedness yields
+fn integer_type(size: usize, signed: bool) -> Type {
+    match (size, signed) {
+        (4, true) => base_type("int", 4, Encoding::Signed),
+        (4, false) => base_type("unsigned int", 4, Encoding::Unsigned),
+        (_, true) => base_type("long", 8, Encoding::Signed),
+        (_, false) => base_type("unsigned long", 8, Encoding::Unsigned),
+    }
+}
+
+/// The C base types by the names gcc gives them, for casts to types the program does not use
+fn builtin_type(name: &str) -> Option<Type> {
+    let (size, encoding) = match name {
+        "char" | "signed char" => (1, Encoding::SignedChar),
+        "unsigned char" => (1, Encoding::UnsignedChar),
+        "_Bool" => (1, Encoding::Boolean),
+        "short int" => (2, Encoding::Signed),
+        "short unsigned int" => (2, Encoding::Unsigned),
+        "int" => (4, Encoding::Signed),
+        "unsigned int" => (4, Encoding::Unsigned),
+        "long int" | "long long int" => (8, Encoding::Signed),
+        "long unsigned int" | "long long unsigned int" => (8, Encoding::Unsigned),
+        "float" => (4, Encoding::Float),
+        "double" => (8, Encoding::Float),
+        _ => return None,
+    };
+    Some(base_type(name, size, encoding))
+}
+
+fn int_type() -> Type {
+    integer_type(4, true)
+}
+
+fn double_type() -> Type {
+    base_type("double", 8, Encoding::Float)
+}
+
+/// Returns the size and signedness of an integer, char, bool or enum type
+fn integer_info(entity_type: &Type) -> (usize, bool) {
+    let stripped = entity_type.strip();
+    let signed = !matches!(
+        stripped.kind,
+        TypeKind::Base(Encoding::Unsigned) | TypeKind::Base(Encoding::UnsignedChar) | TypeKind::Base(Encoding::Boolean)
+    );
+    let size = match stripped.size {
+        0 => 1,
+        size if size > 8 => 8,
+        size => size,
+    };
+    (size, signed)
+}
+
+/// Integer promotion: anything smaller than an int becomes an int
+fn promote((size, signed): (usize, bool)) -> (usize, bool) {
+    if size < 4 {
+        (4, true)
+    } else {
+        (size, signed)
+    }
+}
+
+/// The usual arithmetic conversions of two promoted integer types
+fn common_type(lhs: (usize, bool), rhs: (usize, bool)) -> (usize, bool) {
+    if lhs.1 == rhs.1 {
+        (lhs.0.max(rhs.0), lhs.1)
+    } else {
+        let (unsigned, signed) = if lhs.1 { (rhs, lhs) } else { (lhs, rhs) };
+        if unsigned.0 >= signed.0 {
+            unsigned
+        } else {
+            signed
+        }
+    }
+}
+
+/// Wraps a value around to an integer type, sign extending it if the type is signed
+fn truncate(value: i64, (size, signed): (usize, bool)) -> i64 {
+    let bytes = value.to_le_bytes();
+    if signed {
+        read_signed(&bytes[..size])
+    } else {
+        read_unsigned(&bytes[..size]) as i64
+    }
+}
+
+/// Evaluates expressions against a stopped inferior or a core dump. Variables and registers are
+/// looked up in `frame`.
+pub struct Evaluator<'a> {
+    pub process: &'a dyn ProcessState,
+    pub frame: Frame,
+    /// Debug information of the module the frame is in
+    pub dwarf_data: &'a DwarfData,
+    /// Every loaded module, the program first, for the names the frame's module does not know
+    pub modules: Vec<&'a DwarfData>,
+    pub breakpoints: &'a HashMap<usize, Breakpoint>,
+}
+
+/// Location expressions see the registers of the evaluator's frame. In the outer frames only
+/// the callee-saved registers survive calls, and the xmm registers are only known in the
+/// innermost one.
+impl<'a> FrameContext for Evaluator<'a> {
+    fn register(&self, register: u16) -> Option<u64> {
+        let callee_saved = matches!(register, 3 | 6 | 7 | 12..=16);
+        if !self.frame.innermost && !callee_saved {
+            return None;
+        }
+        match register {
+            17..=32 => {
+                let fpregs = self.process.getfpregs().ok()?;
+                let lane = (register as usize - 17) * 4;
+                Some(fpregs.xmm_space[lane] as u64 | (fpregs.xmm_space[lane + 1] as u64) << 32)
+            }
+            _ => dwarf_register(&self.frame.regs, register),
+        }
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        self.read(addr, len).ok()
+    }
+
+    fn call_frame_cfa(&self) -> Option<usize> {
+        self.process.frame_base(&self.frame, self.dwarf_data, self.breakpoints).ok()
+    }
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn evaluate(&self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Integer(value, entity_type) => Ok(Value::Integer(*value, entity_type.clone())),
+            Expr::Float(value, entity_type) => Ok(Value::Float(*value, entity_type.clone())),
+            Expr::Register(name) => {
+                let value = register_value(&self.frame.regs, name)
+                    .ok_or_else(|| format!("Invalid register `{}'", name))?;
+                // Like gdb, the instruction pointer points to code and the stack registers to
+                // memory of unknown type
+                Ok(match name.as_str() {
+                    "rip" | "pc" => Value::Pointer(value as usize, function_type("void")),
+                    "rsp" | "sp" | "rbp" | "fp" => {
+                        Value::Pointer(value as usize, self.dwarf_data.get_pointee_type(None))
+                    }
+                    "eflags" => Value::Integer(value as i64, int_type()),
+                    _ => Value::Integer(value as i64, integer_type(8, true)),
+                })
+            }
+            Expr::Variable(name) => self.variable(name),
+            Expr::Member(object, field) => match self.evaluate(object)? {
+                Value::OptimizedOut => Err("value has been optimized out".to_string()),
+                Value::Lvalue(addr, entity_type) => {
+                    let member = self.member(&entity_type, field)?;
+                    Ok(Value::Lvalue(addr + member.offset, member.entity_type.clone()))
+                }
+                Value::Bytes(bytes, entity_type) => {
+                    let member = self.member(&entity_type, field)?;
+                    let end = (member.offset + member.entity_type.size).min(bytes.len());
+                    Ok(Value::Bytes(bytes[member.offset.min(end)..end].to_vec(), member.entity_type.clone()))
+                }
+                _ => Err("Attempt to extract a component of a value that is not a structure."
+                    .to_string()),
+            },
+            Expr::Index(array, index) => {
+                let array = self.evaluate(array)?;
+                if let Value::Lvalue(_, entity_type) = &array {
+                    match entity_type.strip().kind {
+                        TypeKind::Array(_, _) | TypeKind::Pointer(_) => {}
+                        _ => {
+                            return Err(format!(
+                                "cannot subscript something of type `{}'",
+                                entity_type.name
+                            ))
+                        }
+                    }
+                }
+                let index = self.evaluate(index)?;
+                match self.binary(BinaryOp::Add, self.rvalue(array)?, self.rvalue(index)?)? {
+                    Value::Pointer(addr, element_type) => self.dereference(addr, element_type),
+                    _ => Err("cannot subscript requested type".to_string()),
+                }
+            }
+            Expr::Unary(UnaryOp::Deref, operand) => match self.rvalue(self.evaluate(operand)?)? {
+                Value::Pointer(addr, pointee) => self.dereference(addr, pointee),
+                _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+            },
+            Expr::Unary(UnaryOp::AddressOf, operand) => match self.evaluate(operand)? {
+                Value::Lvalue(addr, entity_type) => Ok(Value::Pointer(addr, entity_type)),
+                Value::Pointer(addr, pointee) if pointee.kind == TypeKind::Function => {
+                    Ok(Value::Pointer(addr, pointee))
+                }
+                _ => Err("Attempt to take address of value not located in memory.".to_string()),
+            },
+            Expr::Unary(UnaryOp::Not, operand) => {
+                let value = self.evaluate(operand)?;
+                Ok(Value::Integer(!self.truth(value)? as i64, int_type()))
+            }
+            Expr::Unary(op, operand) => match self.rvalue(self.evaluate(operand)?)? {
+                Value::Float(value, entity_type) if *op == UnaryOp::Neg => Ok(Value::Float(-value, entity_type)),
+                Value::Integer(value, entity_type) => {
+                    let promoted = promote(integer_info(&entity_type));
+                    let result = if *op == UnaryOp::Neg { value.wrapping_neg() } else { !value };
+                    Ok(Value::Integer(truncate(result, promoted), integer_type(promoted.0, promoted.1)))
+                }
+                Value::Float(_, _) => Err("Integer only operation.".to_string()),
+                _ => Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+            },
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                let result = self.truth(self.evaluate(lhs)?)? && self.truth(self.evaluate(rhs)?)?;
+                Ok(Value::Integer(result as i64, int_type()))
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                let result = self.truth(self.evaluate(lhs)?)? || self.truth(self.evaluate(rhs)?)?;
+                Ok(Value::Integer(result as i64, int_type()))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.rvalue(self.evaluate(lhs)?)?;
+                let rhs = self.rvalue(self.evaluate(rhs)?)?;
+                self.binary(*op, lhs, rhs)
+            }
+            Expr::Cast(type_name, operand) => {
+                let value = self.evaluate(operand)?;
+                if type_name.pointers > 0 {
+                    let addr = self.integer(value)? as usize;
+                    return Ok(Value::Pointer(addr, self.pointee_type(type_name)?));
+                }
+                if type_name.base == "void" {
+                    return Err("Cannot cast to void here.".to_string());
+                }
+                let target = self.find_type(&type_name.base)?;
+                self.convert(value, &target)
+            }
+        }
+    }
+
+    /// Evaluates an expression to an address: pointers and integers are taken by value, arrays
+    /// decay to their first element
+    pub fn evaluate_address(&self, expr: &Expr) -> Result<usize, String> {
+        Ok(self.evaluate_integer(expr)? as usize)
+    }
+
+    /// Evaluates an expression to an integer, truncating floats
+    pub fn evaluate_integer(&self, expr: &Expr) -> Result<i64, String> {
+        let value = self.evaluate(expr)?;
+        self.integer(value)
+    }
+
+    /// Evaluates an expression and tells whether it is nonzero, as a C condition would
+    pub fn is_true(&self, expr: &Expr) -> Result<bool, String> {
+        let value = self.evaluate(expr)?;
+        self.truth(value)
+    }
+
+    /// Evaluates `<target> = <value>` without carrying it out, since writing to the inferior is
+    /// up to the debugger. Returns the address to write to and the bytes to write there.
+    pub fn assignment(&self, target: &Expr, value: &Expr) -> Result<(usize, Vec<u8>), String> {
+        let (addr, entity_type) = match self.evaluate(target)? {
+            Value::Lvalue(addr, entity_type) => (addr, entity_type),
+            _ => return Err("Left operand of assignment is not an lvalue.".to_string()),
+        };
+        let value = self.evaluate(value)?;
+        Ok((addr, self.encode(value, &entity_type)?))
+    }
+
+    /// Parses an expression, telling types from variables by what is in scope in the frame
+    pub fn parse(&self, input: &str) -> Result<Expr, String> {
+        parse(input, &|name| self.is_type(name))
+    }
+
+    /// Tells whether `name` is a type rather than a variable. As in C, a variable hides a type
+    /// of the same name.
+    pub fn is_type(&self, name: &str) -> bool {
+        let modules = self.search_order();
+        let pc = self.frame.pc();
+        !modules.iter().any(|module| module.get_variable(pc, name).is_some())
+            && modules.iter().any(|module| module.find_type(name).is_some())
+    }
+
+    /// Reads `len` bytes of the inferior's memory, hiding our breakpoints
+    pub fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.process
+            .read_memory(addr, len, self.breakpoints)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+    }
+
+    /// The modules names are looked up in: the frame's own first, then the program and the
+    /// libraries, so that the program's globals are still found from a frame in the C library
+    fn search_order(&self) -> Vec<&'a DwarfData> {
+        let frame_module = self.dwarf_data;
+        std::iter::once(frame_module)
+            .chain(self.modules.iter().cloned().filter(|module| !std::ptr::eq(*module, frame_module)))
+            .collect()
+    }
+
+    /// Looks a type up by name in the loaded modules, falling back to the C base types
+    fn find_type(&self, name: &str) -> Result<Type, String> {
+        self.search_order()
+            .iter()
+            .find_map(|module| module.find_type(name).cloned())
+            .or_else(|| builtin_type(name))
+            .ok_or_else(|| no_type(name))
+    }
+
+    /// Looks a name up as a variable of the current frame or a global, then as an enumeration
+    /// constant, and finally as a function
+    fn variable(&self, name: &str) -> Result<Value, String> {
+        let modules = self.search_order();
+        let pc = self.frame.pc();
+        for module in modules.iter() {
+            if let Some(var) = module.get_variable(pc, name) {
+                let entity_type = var.entity_type.clone();
+                return Ok(match module.locate_variable(var, pc, self)? {
+                    Place::Memory(addr) => Value::Lvalue(addr, entity_type),
+                    Place::Value(bytes) => Value::Bytes(bytes, entity_type),
+                    Place::OptimizedOut => Value::OptimizedOut,
+                });
+            }
+        }
+        for module in modules.iter() {
+            if let Some((value, entity_type)) = module.find_enumerator(name) {
+                return Ok(Value::Integer(value, entity_type.clone()));
+            }
+        }
+        for module in modules.iter() {
+            if let Some((_, func)) = module.find_functions(None, name).first() {
+                let return_type = func.return_type.as_ref().map_or("void", |t| t.name.as_str());
+                return Ok(Value::Pointer(func.address, function_type(return_type)));
+            }
+        }
+        match modules.iter().find_map(|module| module.get_symbol(name)) {
+            Some((addr, _)) => Ok(Value::Pointer(addr, function_type("int"))),
+            None => Err(format!("No symbol \"{}\" in current context.", name)),
+        }
+    }
+
+    fn member<'t>(&self, entity_type: &'t Type, field: &str) -> Result<&'t Member, String> {
+        match &entity_type.strip().kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => members
+                .iter()
+                .find(|member| member.name == field)
+                .ok_or_else(|| format!("There is no member named {}.", field)),
+            _ => Err("Attempt to extract a component of a value that is not a structure.".to_string()),
+        }
+    }
+
+    /// Turns a pointer into the object it points to
+    fn dereference(&self, addr: usize, pointee: Type) -> Result<Value, String> {
+        match pointee.strip().kind {
+            TypeKind::Void => Err("Attempt to take contents of a non-pointer value.".to_string()),
+            TypeKind::Function => Ok(Value::Pointer(addr, pointee)),
+            _ => Ok(Value::Lvalue(addr, pointee)),
+        }
+    }
+
+    /// Loads an lvalue from memory. Arrays decay to a pointer to their first element.
+    fn rvalue(&self, value: Value) -> Result<Value, String> {
+        let (bytes, entity_type) = match value {
+            Value::Lvalue(addr, entity_type) => {
+                let stripped = entity_type.strip();
+                if let TypeKind::Array(element_type, _) = &stripped.kind {
+                    return Ok(Value::Pointer(addr, (**element_type).clone()));
+                }
+                (self.read(addr, stripped.size)?, entity_type)
+            }
+            Value::Bytes(bytes, entity_type) => {
+                if let TypeKind::Array(_, _) = entity_type.strip().kind {
+                    return Err("Attempt to take address of value not located in memory.".to_string());
+                }
+                (bytes, entity_type)
+            }
+            Value::OptimizedOut => return Err("value has been optimized out".to_string()),
+            other => return Ok(other),
+        };
+        let stripped = entity_type.strip();
+        match &stripped.kind {
+            TypeKind::Base(Encoding::Float) => Ok(Value::Float(read_float(&bytes), entity_type)),
+            TypeKind::Base(_) | TypeKind::Enum(_) => {
+                let value = truncate(read_unsigned(&bytes) as i64, integer_info(&entity_type));
+                Ok(Value::Integer(value, entity_type))
+            }
+            TypeKind::Pointer(target) => Ok(Value::Pointer(
+                read_unsigned(&bytes) as usize,
+                self.dwarf_data.get_pointee_type(*target),
+            )),
+            _ => Err(format!("Cannot use a value of type `{}' here", entity_type.name)),
+        }
+    }
+
+    fn integer(&self, value: Value) -> Result<i64, String> {
+        match self.rvalue(value)? {
+            Value::Float(value, _) => Ok(value as i64),
+            Value::Integer(value, _) => Ok(value),
+            Value::Pointer(addr, _) => Ok(addr as i64),
+            Value::Lvalue(_, _) | Value::Bytes(_, _) | Value::OptimizedOut => unreachable!(),
+        }
+    }
+
+    fn float(&self, value: Value) -> Result<f64, String> {
+        match self.rvalue(value)? {
+            Value::Float(value, _) => Ok(value),
+            Value::Integer(value, entity_type) if !integer_info(&entity_type).1 => Ok(value as u64 as f64),
+            value => Ok(self.integer(value)? as f64),
+        }
+    }
+
+    fn truth(&self, value: Value) -> Result<bool, String> {
+        match self.rvalue(value)? {
+            Value::Float(value, _) => Ok(value != 0.0),
+            value => Ok(self.integer(value)? != 0),
+        }
+    }
+
+    /// Converts a value to `target`, as a cast or an assignment does
+    fn convert(&self, value: Value, target: &Type) -> Result<Value, String> {
+        let stripped = target.strip();
+        match &stripped.kind {
+            TypeKind::Base(Encoding::Float) => {
+                let value = self.float(value)?;
+                let value = if stripped.size == 4 { value as f32 as f64 } else { value };
+                Ok(Value::Float(value, target.clone()))
+            }
+            TypeKind::Base(Encoding::Boolean) => Ok(Value::Integer(self.truth(value)? as i64, target.clone())),
+            TypeKind::Base(_) | TypeKind::Enum(_) => {
+                let value = truncate(self.integer(value)?, integer_info(target));
+                Ok(Value::Integer(value, target.clone()))
+            }
+            TypeKind::Pointer(pointee) => {
+                let addr = self.integer(value)? as usize;
+                Ok(Value::Pointer(addr, self.dwarf_data.get_pointee_type(*pointee)))
+            }
+            _ => Err(format!("Invalid cast to `{}'.", target.name)),
+        }
+    }
+
+    /// Converts a value to the bytes of an object of type `entity_type`, the way C converts
+    /// the right-hand side of an assignment
+    fn encode(&self, value: Value, entity_type: &Type) -> Result<Vec<u8>, String> {
+        let stripped = entity_type.strip();
+        if let TypeKind::Struct(_) | TypeKind::Union(_) | TypeKind::Array(_, _) = stripped.kind {
+            return match value {
+                Value::Lvalue(addr, value_type) if value_type.strip().name == stripped.name => {
+                    self.read(addr, stripped.size)
+                }
+                _ => Err(format!("Invalid cast to `{}'.", entity_type.name)),
+            };
+        }
+        match self.convert(value, entity_type)? {
+            Value::Float(value, _) if stripped.size == 4 => Ok((value as f32).to_bits().to_le_bytes().to_vec()),
+            Value::Float(value, _) if stripped.size == 8 => Ok(value.to_bits().to_le_bytes().to_vec()),
+            Value::Integer(value, _) => Ok(value.to_le_bytes()[..stripped.size.min(8)].to_vec()),
+            Value::Pointer(addr, _) => Ok(addr.to_le_bytes()[..stripped.size.min(8)].to_vec()),
+            _ => Err(format!("Cannot assign to a value of type `{}'.", entity_type.name)),
+        }
+    }
+
+    /// Returns the type a pointer named in a cast points to, e.g. `int *` for `(int **)`
+    fn pointee_type(&self, type_name: &TypeName) -> Result<Type, String> {
+        if type_name.pointers == 1 && type_name.base != "void" {
+            return self.find_type(&type_name.base);
+        }
+        let base = if type_name.base == "void" {
+            None
+        } else {
+            let offset = self.dwarf_data.find_type_offset(&type_name.base);
+            Some(offset.ok_or_else(|| no_type(&type_name.base))?)
+        };
+        if type_name.pointers == 1 {
+            return Ok(self.dwarf_data.get_pointee_type(base));
+        }
+        // Pointer types refer to what they point to by offset, so the intermediate levels have
+        // to exist in the debug information
+        let name = format!("{} {}", type_name.base, "*".repeat(type_name.pointers - 1));
+        let mut target = base;
+        for _ in 2..type_name.pointers {
+            target = Some(self.dwarf_data.find_pointer_type(target).ok_or_else(|| no_type(&name))?);
+        }
+        Ok(Type::new(name, 8, TypeKind::Pointer(target)))
+    }
+
+    /// Applies a binary operator to two rvalues, following C's conversion rules
+    fn binary(&self, op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+        if let (Value::Pointer(_, _), _) | (_, Value::Pointer(_, _)) = (&lhs, &rhs) {
+            return pointer_arithmetic(op, lhs, rhs);
+        }
+        if let (Value::Float(_, _), _) | (_, Value::Float(_, _)) = (&lhs, &rhs) {
+            // float op float stays a float, anything else involving a double is a double
+            let is_float = |value: &Value| match value {
+                Value::Float(_, entity_type) => entity_type.strip().size == 4,
+                _ => true,
+            };
+            let result_type = if is_float(&lhs) && is_float(&rhs) {
+                base_type("float", 4, Encoding::Float)
+            } else {
+                double_type()
+            };
+            return float_arithmetic(op, self.float(lhs)?, self.float(rhs)?, result_type);
+        }
+        let (lhs, lhs_type, rhs, rhs_type) = match (lhs, rhs) {
+            (Value::Integer(lhs, lhs_type), Value::Integer(rhs, rhs_type)) => (lhs, lhs_type, rhs, rhs_type),
+            _ => return Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+        };
+        let lhs_info = promote(integer_info(&lhs_type));
+        if let BinaryOp::Shl | BinaryOp::Shr = op {
+            let shift = rhs as u32;
+            let result = match op {
+                BinaryOp::Shl => lhs.wrapping_shl(shift),
+                _ if lhs_info.1 => lhs.wrapping_shr(shift),
+                _ => (truncate(lhs, (lhs_info.0, false)) as u64).wrapping_shr(shift) as i64,
+            };
+            return Ok(Value::Integer(truncate(result, lhs_info), integer_type(lhs_info.0, lhs_info.1)));
+        }
+        let common = common_type(lhs_info, promote(integer_info(&rhs_type)));
+        let (lhs, rhs) = (truncate(lhs, common), truncate(rhs, common));
+        let comparison = |result: bool| Ok(Value::Integer(result as i64, int_type()));
+        let (signed, unsigned_lhs, unsigned_rhs) = (common.1, lhs as u64, rhs as u64);
+        let result = match op {
+            BinaryOp::Lt if signed => return comparison(lhs < rhs),
+            BinaryOp::Le if signed => return comparison(lhs <= rhs),
+            BinaryOp::Gt if signed => return comparison(lhs > rhs),
+            BinaryOp::Ge if signed => return comparison(lhs >= rhs),
+            BinaryOp::Lt => return comparison(unsigned_lhs < unsigned_rhs),
+            BinaryOp::Le => return comparison(unsigned_lhs <= unsigned_rhs),
+            BinaryOp::Gt => return comparison(unsigned_lhs > unsigned_rhs),
+            BinaryOp::Ge => return comparison(unsigned_lhs >= unsigned_rhs),
+            BinaryOp::Eq => return comparison(lhs == rhs),
+            BinaryOp::Ne => return comparison(lhs != rhs),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err("Division by zero".to_string()),
+            BinaryOp::Div if signed => lhs.wrapping_div(rhs),
+            BinaryOp::Rem if signed => lhs.wrapping_rem(rhs),
+            BinaryOp::Div => (unsigned_lhs / unsigned_rhs) as i64,
+            BinaryOp::Rem => (unsigned_lhs % unsigned_rhs) as i64,
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::BitXor => lhs ^ rhs,
+            BinaryOp::BitOr => lhs | rhs,
+            BinaryOp::Shl | BinaryOp::Shr | BinaryOp::And | BinaryOp::Or => unreachable!(),
+        };
+        Ok(Value::Integer(truncate(result, common), integer_type(common.0, common.1)))
+    }
+}
+
+/// The type of a function returning `return_type`, as functions and the instruction pointer
+/// evaluate to pointers to it
+fn function_type(return_type: &str) -> Type {
+    Type::new(format!("{} ()", return_type), 1, TypeKind::Function)
+}
+
+fn no_type(name: &str) -> String {
+    format!("No symbol \"{}\" in current context.", name)
+}
+
+/// Adds an integer to a pointer or subtracts it, in units of the type pointed to, subtracts
+/// two pointers, or compares them
+fn pointer_arithmetic(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    // Arithmetic on void pointers goes byte by byte, as in GNU C
+    let element_size = |pointee: &Type| pointee.strip().size.max(1) as i64;
+    let comparison = |result: bool| Ok(Value::Integer(result as i64, int_type()));
+    match (op, lhs, rhs) {
+        (BinaryOp::Add, Value::Pointer(addr, pointee), Value::Integer(offset, _))
+        | (BinaryOp::Add, Value::Integer(offset, _), Value::Pointer(addr, pointee)) => {
+            let addr = (addr as i64).wrapping_add(offset.wrapping_mul(element_size(&pointee)));
+            Ok(Value::Pointer(addr as usize, pointee))
+        }
+        (BinaryOp::Sub, Value::Pointer(addr, pointee), Value::Integer(offset, _)) => {
+            let addr = (addr as i64).wrapping_sub(offset.wrapping_mul(element_size(&pointee)));
+            Ok(Value::Pointer(addr as usize, pointee))
+        }
+        (BinaryOp::Sub, Value::Pointer(lhs, pointee), Value::Pointer(rhs, _)) => {
+            let difference = (lhs as i64).wrapping_sub(rhs as i64) / element_size(&pointee);
+            Ok(Value::Integer(difference, integer_type(8, true)))
+        }
+        (op, lhs, rhs) => {
+            let address = |value: Value| match value {
+                Value::Pointer(addr, _) => Some(addr as u64),
+                Value::Integer(value, _) => Some(value as u64),
+                _ => None,
+            };
+            let (lhs, rhs) = match (address(lhs), address(rhs)) {
+                (Some(lhs), Some(rhs)) => (lhs, rhs),
+                _ => return Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+            };
+            match op {
+                BinaryOp::Lt => comparison(lhs < rhs),
+                BinaryOp::Le => comparison(lhs <= rhs),
+                BinaryOp::Gt => comparison(lhs > rhs),
+                BinaryOp::Ge => comparison(lhs >= rhs),
+                BinaryOp::Eq => comparison(lhs == rhs),
+                BinaryOp::Ne => comparison(lhs != rhs),
+                _ => Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+            }
+        }
+    }
+}
+
+/// Applies a binary operator to two floating point numbers
+fn float_arithmetic(op: BinaryOp, lhs: f64, rhs: f64, result_type: Type) -> Result<Value, String> {
+    let comparison = |result: bool| Ok(Value::Integer(result as i64, int_type()));
+    let result = match op {
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div => lhs / rhs,
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        BinaryOp::Lt => return comparison(lhs < rhs),
+        BinaryOp::Le => return comparison(lhs <= rhs),
+        BinaryOp::Gt => return comparison(lhs > rhs),
+        BinaryOp::Ge => return comparison(lhs >= rhs),
+        BinaryOp::Eq => return comparison(lhs == rhs),
+        BinaryOp::Ne => return comparison(lhs != rhs),
+        _ => return Err("Integer only operation.".to_string()),
+    };
+    let result = if result_type.size == 4 { result as f32 as f64 } else { result };
+    Ok(Value::Float(result, result_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::errno::Errno;
+
+    /// Where `Memory` keeps the only long it has
+    const ADDRESS: usize = 0x1000;
+
+    /// A process whose memory is a single long holding 42 at `ADDRESS`
+    struct Memory;
+
+    impl ProcessState for Memory {
+        fn getregs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+            Err(nix::Error::Sys(Errno::ESRCH))
+        }
+
+        fn getfpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+            Err(nix::Error::Sys(Errno::ESRCH))
+        }
+
+        fn read_memory(&self, addr: usize, len: usize, _: &HashMap<usize, Breakpoint>) -> Result<Vec<u8>, nix::Error> {
+            let bytes = 42u64.to_le_bytes();
+            let start = addr.wrapping_sub(ADDRESS);
+            match bytes.get(start..start.wrapping_add(len)) {
+                Some(bytes) => Ok(bytes.to_vec()),
+                None => Err(nix::Error::Sys(Errno::EIO)),
+            }
+        }
+    }
+
+    /// The typedefs the tests use. Everything else is left to the built-in C base types.
+    fn typedefs() -> Vec<Type> {
+        let typedef = |name: &str, target: Type| {
+            Type::new(name.to_string(), target.size, TypeKind::Typedef(Box::new(target)))
+        };
+        vec![
+            typedef("size_t", builtin_type("long unsigned int").unwrap()),
+            typedef("uint8_t", builtin_type("unsigned char").unwrap()),
+        ]
+    }
+
+    /// Tells the typedefs from `typedefs` from other names, like the evaluator does
+    fn is_type(name: &str) -> bool {
+        typedefs().iter().any(|entity_type| entity_type.name == name)
+    }
+
+    /// Evaluates an expression to its value and the name of its type
+    fn evaluate(input: &str) -> Result<(i64, String), String> {
+        let dwarf_data = DwarfData::from_types(typedefs());
+        let evaluator = Evaluator {
+            process: &Memory,
+            frame: Frame {
+                regs: unsafe { std::mem::zeroed() },
+                innermost: true,
+            },
+            dwarf_data: &dwarf_data,
+            modules: vec![&dwarf_data],
+            breakpoints: &HashMap::new(),
+        };
+        match evaluator.rvalue(evaluator.evaluate(&evaluator.parse(input)?)?)? {
+            Value::Integer(value, entity_type) => Ok((value, entity_type.name)),
+            other => panic!("{} is not an integer: {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn literal_types() {
+        assert_eq!(evaluate("1"), Ok((1, "int".to_string())));
+        assert_eq!(evaluate("4294967295"), Ok((4294967295, "long".to_string())));
+        assert_eq!(evaluate("0xffffffff"), Ok((4294967295, "unsigned int".to_string())));
+        assert_eq!(evaluate("2147483648u"), Ok((2147483648, "unsigned int".to_string())));
+        assert_eq!(evaluate("1ul"), Ok((1, "unsigned long".to_string())));
+        assert_eq!(evaluate("'a'"), Ok((97, "char".to_string())));
+    }
+
+    #[test]
+    fn integer_overflow() {
+        assert_eq!(evaluate("0x7fffffff + 1"), Ok((i32::MIN as i64, "int".to_string())));
+        assert_eq!(evaluate("0xffffffff + 1"), Ok((0, "unsigned int".to_string())));
+        assert_eq!(evaluate("4294967295 + 1"), Ok((4294967296, "long".to_string())));
+    }
+
+    #[test]
+    fn mixed_signedness_comparisons() {
+        assert_eq!(evaluate("1u < -1"), Ok((1, "int".to_string())));
+        assert_eq!(evaluate("-1 < 1u"), Ok((0, "int".to_string())));
+        assert_eq!(evaluate("-1L < 1u"), Ok((1, "int".to_string())));
+        assert_eq!(evaluate("-1L < 1ul"), Ok((0, "int".to_string())));
+        assert_eq!(evaluate("-1 == 0xffffffff"), Ok((1, "int".to_string())));
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(evaluate("-7 / 2"), Ok((-3, "int".to_string())));
+        assert_eq!(evaluate("-7 % 2"), Ok((-1, "int".to_string())));
+        assert_eq!(evaluate("-7 / 2u"), Ok((2147483644, "unsigned int".to_string())));
+        assert_eq!(evaluate("1 / 0"), Err("Division by zero".to_string()));
+        assert_eq!(evaluate("1 % 0"), Err("Division by zero".to_string()));
+        assert_eq!(evaluate("1 / (2 - 2)"), Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok((7, "int".to_string())));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok((9, "int".to_string())));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok((3, "int".to_string())));
+        assert_eq!(evaluate("1 << 2 + 1"), Ok((8, "int".to_string())));
+        assert_eq!(evaluate("6 & 3 == 3"), Ok((0, "int".to_string())));
+        assert_eq!(evaluate("1 || 0 && 0"), Ok((1, "int".to_string())));
+        assert_eq!(evaluate("-2 * -3"), Ok((6, "int".to_string())));
+        assert_eq!(evaluate("!0 + ~0"), Ok((0, "int".to_string())));
+    }
+
+    #[test]
+    fn casts() {
+        assert_eq!(evaluate("(char)300"), Ok((44, "char".to_string())));
+        assert_eq!(evaluate("(unsigned char)-1"), Ok((255, "unsigned char".to_string())));
+        assert_eq!(evaluate("(long long)5"), Ok((5, "long long int".to_string())));
+        assert_eq!(evaluate("(unsigned short)-1"), Ok((65535, "short unsigned int".to_string())));
+        assert_eq!(evaluate("*(long *)0x1000"), Ok((42, "long int".to_string())));
+    }
+
+    #[test]
+    fn typedef_casts() {
+        assert_eq!(evaluate("(size_t)-1"), Ok((-1, "size_t".to_string())));
+        assert_eq!(evaluate("(size_t)-1 > 0"), Ok((1, "int".to_string())));
+        assert_eq!(evaluate("(uint8_t)~0"), Ok((255, "uint8_t".to_string())));
+        assert_eq!(evaluate("(uint8_t)*(long *)0x1000"), Ok((42, "uint8_t".to_string())));
+        assert_eq!(evaluate("(size_t)+1"), Ok((1, "size_t".to_string())));
+    }
+
+    #[test]
+    fn typedef_or_variable() {
+        let variable = |name: &str| Box::new(Expr::Variable(name.to_string()));
+        let size_t = TypeName {
+            base: "size_t".to_string(),
+            pointers: 0,
+        };
+        assert_eq!(
+            parse("(size_t)*p", &is_type),
+            Ok(Expr::Cast(size_t.clone(), Box::new(Expr::Unary(UnaryOp::Deref, variable("p")))))
+        );
+        assert_eq!(
+            parse("(size_t)&x", &is_type),
+            Ok(Expr::Cast(size_t, Box::new(Expr::Unary(UnaryOp::AddressOf, variable("x")))))
+        );
+        assert_eq!(parse("(n)*p", &is_type), Ok(Expr::Binary(BinaryOp::Mul, variable("n"), variable("p"))));
+        assert!(matches!(parse("(n)-1", &is_type), Ok(Expr::Binary(BinaryOp::Sub, _, _))));
+    }
+}
@@ -6,17 +6,23 @@
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
 use gimli;
-use gimli::{UnitOffset, UnitSectionOffset};
-use object::Object;
+use gimli::{UnitOffset, UnitSectionOffset, UnwindSection};
+use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    Encoding, Expression, File, FrameContext, FrameRule, Function, Line, Location, Member, Place,
+    Qualifier, SavedRegister, Type, TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -49,11 +55,27 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
 
+        // Collect the types first, since variables may refer to types that are declared
+        // further down in the unit.
+        for (offset, entity_type) in load_types(&unit, &dwarf)? {
+            offset_to_type.insert(offset, entity_type);
+        }
+
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // Depth of the function whose variables are being collected, and of the inlined
+        // function we are inside of, whose variables belong to neither
+        let mut function_depth = None;
+        let mut inlined_depth = None;
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
+            if matches!(function_depth, Some(function_depth) if depth <= function_depth) {
+                function_depth = None;
+            }
+            if matches!(inlined_depth, Some(inlined_depth) if depth <= inlined_depth) {
+                inlined_depth = None;
+            }
             // Update the offset_to_type mapping for types
             // Update the variable list for formal params/variables
             match entry.tag() {
@@ -74,35 +96,9 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
+                    let mut has_code = false;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -122,6 +118,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 //println!("low pc {:?}", attr.value());
                                 if let Ok(DebugValue::Uint(low_pc)) = val {
                                     func.address = low_pc.try_into().unwrap();
+                                    has_code = true;
                                 }
                             }
                             gimli::DW_AT_decl_line => {
@@ -129,15 +126,49 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_type => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    func.return_type = offset_to_type.get(&offset).cloned();
+                                }
+                            }
+                            gimli::DW_AT_frame_base => {
+                                if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+                                    func.frame_base = Some(to_expression(data, &unit)?);
+                                }
+                            }
+                            gimli::DW_AT_abstract_origin | gimli::DW_AT_specification => {
+                                // The out-of-line copy of an inlined function only refers to
+                                // the declaration for its name and type
+                                if let Some(origin) = get_origin(&attr, &unit) {
+                                    if let Some(name) = get_name(&origin, &unit, &dwarf) {
+                                        func.name = name;
+                                    }
+                                    if let Some(offset) = get_type_ref(&origin, &unit, &dwarf) {
+                                        func.return_type = offset_to_type.get(&offset).cloned();
+                                    }
+                                    if let Some(line_number) = get_udata(&origin, gimli::DW_AT_decl_line) {
+                                        func.line_number = line_number;
+                                    }
+                                }
+                            }
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
+                    // Declarations of functions defined elsewhere, such as the prototypes of
+                    // library functions, have no code of their own
+                    if has_code && function_depth.is_none() {
+                        compilation_units.last_mut().unwrap().functions.push(func);
+                        function_depth = Some(depth);
+                    }
+                }
+                gimli::DW_TAG_inlined_subroutine if inlined_depth.is_none() => {
+                    inlined_depth = Some(depth);
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
                     let mut entity_type: Option<Type> = None;
                     let mut location: Option<Location> = None;
+                    let mut const_value = None;
                     let mut line_number = 0;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
@@ -157,21 +188,47 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                                location = get_location(&attr, &unit, &dwarf)?;
+                            }
+                            gimli::DW_AT_const_value => {
+                                const_value = Some(attr.value());
                             }
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
                                     line_number = num;
                                 }
                             }
+                            gimli::DW_AT_abstract_origin => {
+                                if let Some(origin) = get_origin(&attr, &unit) {
+                                    if let Some(origin_name) = get_name(&origin, &unit, &dwarf) {
+                                        name = origin_name;
+                                    }
+                                    if let Some(offset) = get_type_ref(&origin, &unit, &dwarf) {
+                                        entity_type = offset_to_type.get(&offset).cloned();
+                                    }
+                                    if let Some(num) = get_udata(&origin, gimli::DW_AT_decl_line) {
+                                        line_number = num as u64;
+                                    }
+                                }
+                            }
                             _ => {}
                         }
                     }
+                    // A variable the compiler turned into a constant has its value instead of
+                    // a location, which DW_OP_implicit_value can stand for
+                    if let (None, Some(value), Some(dtype)) = (&location, &const_value, &entity_type) {
+                        location = const_value_location(value, dtype.size, &unit)?;
+                    }
+                    // Locals without a location were optimized out entirely. Globals without
+                    // one are only declarations of a variable defined elsewhere.
+                    let in_function = depth > 1 && function_depth.is_some() && inlined_depth.is_none();
+                    if location.is_none() && in_function && !name.is_empty() {
+                        location = Some(Location::List(Vec::new()));
+                    }
                     if entity_type.is_some() && location.is_some() {
                         let var = Variable {
                             name,
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
                             entity_type: entity_type.unwrap(),
                             location: location.unwrap(),
                             line_number: line_number.try_into().unwrap(),
@@ -182,7 +239,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 .unwrap()
                                 .global_variables
                                 .push(var);
-                        } else if depth > 1 {
+                        } else if in_function {
                             compilation_units
                                 .last_mut()
                                 .unwrap()
@@ -200,12 +257,19 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
 
-        // Get line numbers
+        // Get line numbers. Paths in the line table and the unit's name may be relative to the
+        // compilation directory.
+        let comp_dir = match &unit.comp_dir {
+            Some(dir) => path::PathBuf::from(dir.to_string_lossy().as_ref()),
+            None => path::PathBuf::new(),
+        };
+        let unit_path = comp_dir.join(&compilation_units.last().unwrap().name);
         if let Some(program) = unit.line_program.clone() {
             // Iterate over the line program rows.
             let mut rows = program.rows();
             while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
+                // Only rows marked is_stmt are recommended places for breakpoints and line steps
+                if !row.end_sequence() && row.is_stmt() {
                     // Determine the path. Real applications should cache this for performance.
                     let mut path = path::PathBuf::new();
                     if let Some(file) = row.file(header) {
@@ -220,10 +284,10 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
+                    // Lines from headers are left out
                     let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                        .last_mut()
+                        .filter(|_| comp_dir.join(&path) == unit_path);
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -240,7 +304,431 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+/// A type DIE as it appears in the unit, before references to other types are resolved
+struct RawType {
+    tag: gimli::DwTag,
+    name: Option<String>,
+    encoding: Option<gimli::DwAte>,
+    size: Option<usize>,
+    type_ref: Option<usize>,
+    members: Vec<RawMember>,
+    dimensions: Vec<Option<usize>>,
+    enumerators: Vec<(String, i64)>,
+}
+
+struct RawMember {
+    name: String,
+    type_ref: Option<usize>,
+    offset: usize,
+    bit_size: Option<usize>,
+    bit_offset: Option<usize>,
+}
+
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn get_type_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    let attr = entry.attr(gimli::DW_AT_type).ok()??;
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn get_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    let attr = entry.attr(gimli::DW_AT_name).ok()??;
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn get_udata<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>, name: gimli::DwAt) -> Option<usize> {
+    entry.attr(name).ok()??.udata_value()?.try_into().ok()
+}
+
+/// Reads every type DIE of a unit and resolves them into `Type` trees, keyed by their offset in
+/// .debug_info (the same offsets DW_AT_type refers to).
+fn load_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<HashMap<usize, Type>, Error> {
+    let mut raw_types: HashMap<usize, RawType> = HashMap::new();
+    // parents[depth] is the offset of the type DIE at that depth of the current DFS path
+    let mut parents: Vec<Option<usize>> = Vec::new();
+    let mut depth: isize = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        let depth = depth as usize;
+        parents.truncate(depth);
+        let offset = section_offset(entry.offset(), unit);
+        let parent = if depth > 0 {
+            parents.get(depth - 1).cloned().flatten()
+        } else {
+            None
+        };
+        match entry.tag() {
+            gimli::DW_TAG_base_type
+            | gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_array_type
+            | gimli::DW_TAG_enumeration_type
+            | gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_subroutine_type
+            | gimli::DW_TAG_unspecified_type => {
+                let mut raw = RawType {
+                    tag: entry.tag(),
+                    name: get_name(entry, unit, dwarf),
+                    encoding: None,
+                    size: get_udata(entry, gimli::DW_AT_byte_size),
+                    type_ref: get_type_ref(entry, unit, dwarf),
+                    members: Vec::new(),
+                    dimensions: Vec::new(),
+                    enumerators: Vec::new(),
+                };
+                if let Ok(Some(gimli::AttributeValue::Encoding(encoding))) =
+                    entry.attr_value(gimli::DW_AT_encoding)
+                {
+                    raw.encoding = Some(encoding);
+                }
+                raw_types.insert(offset, raw);
+                parents.push(Some(offset));
+                continue;
+            }
+            gimli::DW_TAG_member => {
+                if let Some(parent) = parent.and_then(|parent| raw_types.get_mut(&parent)) {
+                    let member_offset = match entry.attr_value(gimli::DW_AT_data_member_location) {
+                        Ok(Some(gimli::AttributeValue::Exprloc(expr))) => {
+                            let mut pc = expr.0.clone();
+                            match gimli::Operation::parse(&mut pc, unit.encoding()) {
+                                Ok(gimli::Operation::PlusConstant { value }) => value as usize,
+                                _ => 0,
+                            }
+                        }
+                        Ok(Some(value)) => value.udata_value().unwrap_or(0) as usize,
+                        _ => 0,
+                    };
+                    let bit_size = get_udata(entry, gimli::DW_AT_bit_size);
+                    // DWARF 2/3 style bit fields count DW_AT_bit_offset from the most significant
+                    // bit of a storage unit of DW_AT_byte_size bytes
+                    let bit_offset = match get_udata(entry, gimli::DW_AT_data_bit_offset) {
+                        Some(bit_offset) => Some(bit_offset),
+                        None => match (
+                            get_udata(entry, gimli::DW_AT_bit_offset),
+                            get_udata(entry, gimli::DW_AT_byte_size),
+                            bit_size,
+                        ) {
+                            (Some(bit_offset), Some(byte_size), Some(bit_size)) => {
+                                Some(member_offset * 8 + byte_size * 8 - bit_offset - bit_size)
+                            }
+                            _ => None,
+                        },
+                    };
+                    parent.members.push(RawMember {
+                        name: get_name(entry, unit, dwarf).unwrap_or_default(),
+                        type_ref: get_type_ref(entry, unit, dwarf),
+                        offset: member_offset,
+                        bit_size,
+                        bit_offset,
+                    });
+                }
+            }
+            gimli::DW_TAG_subrange_type => {
+                if let Some(parent) = parent.and_then(|parent| raw_types.get_mut(&parent)) {
+                    let count = match get_udata(entry, gimli::DW_AT_count) {
+                        Some(count) => Some(count),
+                        None => entry
+                            .attr(gimli::DW_AT_upper_bound)
+                            .ok()
+                            .and_then(|attr| attr?.sdata_value())
+                            .map(|upper_bound| (upper_bound + 1) as usize),
+                    };
+                    parent.dimensions.push(count);
+                }
+            }
+            gimli::DW_TAG_enumerator => {
+                if let Some(parent) = parent.and_then(|parent| raw_types.get_mut(&parent)) {
+                    let value = entry
+                        .attr(gimli::DW_AT_const_value)
+                        .ok()
+                        .and_then(|attr| attr?.sdata_value())
+                        .unwrap_or(0);
+                    parent
+                        .enumerators
+                        .push((get_name(entry, unit, dwarf).unwrap_or_default(), value));
+                }
+            }
+            _ => {}
+        }
+        parents.push(None);
+    }
+
+    let mut types = HashMap::new();
+    let offsets: Vec<usize> = raw_types.keys().cloned().collect();
+    for offset in offsets {
+        build_type(Some(offset), &raw_types, &mut types);
+    }
+    Ok(types)
+}
+
+/// Builds the `Type` for the DIE at `offset`, recursing into everything but pointer targets so
+/// that self-referential structs terminate. `None` stands for void.
+fn build_type(
+    offset: Option<usize>,
+    raw_types: &HashMap<usize, RawType>,
+    types: &mut HashMap<usize, Type>,
+) -> Type {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => return Type::new("void".to_string(), 0, TypeKind::Void),
+    };
+    if let Some(entity_type) = types.get(&offset) {
+        return entity_type.clone();
+    }
+    let raw = match raw_types.get(&offset) {
+        Some(raw) => raw,
+        None => return Type::new("<unknown>".to_string(), 0, TypeKind::Unknown),
+    };
+    let entity_type = match raw.tag {
+        gimli::DW_TAG_base_type => {
+            let kind = match raw.encoding {
+                Some(gimli::DW_ATE_signed) => TypeKind::Base(Encoding::Signed),
+                Some(gimli::DW_ATE_unsigned) => TypeKind::Base(Encoding::Unsigned),
+                Some(gimli::DW_ATE_signed_char) => TypeKind::Base(Encoding::SignedChar),
+                Some(gimli::DW_ATE_unsigned_char) => TypeKind::Base(Encoding::UnsignedChar),
+                Some(gimli::DW_ATE_float) => TypeKind::Base(Encoding::Float),
+                Some(gimli::DW_ATE_boolean) => TypeKind::Base(Encoding::Boolean),
+                _ => TypeKind::Unknown,
+            };
+            Type::new(type_name(Some(offset), raw_types), raw.size.unwrap_or(0), kind)
+        }
+        gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => Type::new(
+            type_name(Some(offset), raw_types),
+            raw.size.unwrap_or(8),
+            TypeKind::Pointer(raw.type_ref),
+        ),
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+            let members = raw
+                .members
+                .iter()
+                .map(|member| Member {
+                    name: member.name.clone(),
+                    entity_type: build_type(member.type_ref, raw_types, types),
+                    offset: member.offset,
+                    bit_size: member.bit_size,
+                    bit_offset: member.bit_offset,
+                })
+                .collect();
+            let kind = if raw.tag == gimli::DW_TAG_union_type {
+                TypeKind::Union(members)
+            } else {
+                TypeKind::Struct(members)
+            };
+            Type::new(type_name(Some(offset), raw_types), raw.size.unwrap_or(0), kind)
+        }
+        gimli::DW_TAG_array_type => {
+            // int a[2][3] has two subranges; build it as an array of 2 arrays of 3 ints
+            let mut entity_type = build_type(raw.type_ref, raw_types, types);
+            let dimensions = if raw.dimensions.is_empty() {
+                vec![None]
+            } else {
+                raw.dimensions.clone()
+            };
+            let element_name = entity_type.name.clone();
+            for i in (0..dimensions.len()).rev() {
+                let suffix: Vec<String> = dimensions[i..]
+                    .iter()
+                    .map(|count| format!("[{}]", count.map(|count| count.to_string()).unwrap_or_default()))
+                    .collect();
+                let name = format!("{} {}", element_name, suffix.join(""));
+                let size = entity_type.size * dimensions[i].unwrap_or(0);
+                entity_type = Type::new(name, size, TypeKind::Array(Box::new(entity_type), dimensions[i]));
+            }
+            entity_type
+        }
+        gimli::DW_TAG_enumeration_type => Type::new(
+            type_name(Some(offset), raw_types),
+            raw.size.unwrap_or(4),
+            TypeKind::Enum(raw.enumerators.clone()),
+        ),
+        gimli::DW_TAG_typedef => {
+            let target = build_type(raw.type_ref, raw_types, types);
+            Type::new(type_name(Some(offset), raw_types), target.size, TypeKind::Typedef(Box::new(target)))
+        }
+        gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type | gimli::DW_TAG_restrict_type => {
+            let target = build_type(raw.type_ref, raw_types, types);
+            let qualifier = match raw.tag {
+                gimli::DW_TAG_const_type => Qualifier::Const,
+                gimli::DW_TAG_volatile_type => Qualifier::Volatile,
+                _ => Qualifier::Restrict,
+            };
+            Type::new(
+                type_name(Some(offset), raw_types),
+                target.size,
+                TypeKind::Qualified(qualifier, Box::new(target)),
+            )
+        }
+        gimli::DW_TAG_subroutine_type => {
+            Type::new(type_name(Some(offset), raw_types), 0, TypeKind::Function)
+        }
+        _ => Type::new(type_name(Some(offset), raw_types), raw.size.unwrap_or(0), TypeKind::Void),
+    };
+    types.insert(offset, entity_type.clone());
+    entity_type
+}
+
+/// Spells out a type the way C would, e.g. "const char *" or "struct node"
+fn type_name(offset: Option<usize>, raw_types: &HashMap<usize, RawType>) -> String {
+    let raw = match offset {
+        Some(offset) => match raw_types.get(&offset) {
+            Some(raw) => raw,
+            None => return "<unknown>".to_string(),
+        },
+        None => return "void".to_string(),
+    };
+    let tag_name = |keyword: &str| match &raw.name {
+        Some(name) => format!("{} {}", keyword, name),
+        None => format!("{} {{...}}", keyword),
+    };
+    match raw.tag {
+        gimli::DW_TAG_structure_type => tag_name("struct"),
+        gimli::DW_TAG_class_type => tag_name("class"),
+        gimli::DW_TAG_union_type => tag_name("union"),
+        gimli::DW_TAG_enumeration_type => tag_name("enum"),
+        gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+            let sigil = if raw.tag == gimli::DW_TAG_pointer_type { "*" } else { "&" };
+            let target = raw.type_ref.and_then(|target| raw_types.get(&target));
+            match target {
+                Some(target) if target.tag == gimli::DW_TAG_subroutine_type => {
+                    format!("{} ({})()", type_name(target.type_ref, raw_types), sigil)
+                }
+                _ => format!("{} {}", type_name(raw.type_ref, raw_types), sigil),
+            }
+        }
+        gimli::DW_TAG_const_type => format!("const {}", type_name(raw.type_ref, raw_types)),
+        gimli::DW_TAG_volatile_type => format!("volatile {}", type_name(raw.type_ref, raw_types)),
+        gimli::DW_TAG_restrict_type => format!("{} restrict", type_name(raw.type_ref, raw_types)),
+        gimli::DW_TAG_subroutine_type => format!("{} ()", type_name(raw.type_ref, raw_types)),
+        gimli::DW_TAG_array_type => format!("{} []", type_name(raw.type_ref, raw_types)),
+        _ => raw.name.clone().unwrap_or_else(|| "<unknown>".to_string()),
+    }
+}
+
+/// Register number of the return address column in x86-64 call frame information
+const RETURN_ADDRESS_REGISTER: u16 = 16;
+
+/// The .eh_frame and .debug_frame sections, kept around to unwind the stack later on
+pub struct CallFrameInfo {
+    eh_frame: Vec<u8>,
+    eh_frame_address: u64,
+    debug_frame: Vec<u8>,
+    text_address: u64,
+    endian: gimli::RunTimeEndian,
+}
+
+impl CallFrameInfo {
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let section_data = |name| {
+            object
+                .section_data_by_name(name)
+                .map(|data| data.to_vec())
+                .unwrap_or_default()
+        };
+        let section_address = |name| object.section_by_name(name).map(|s| s.address()).unwrap_or(0);
+        CallFrameInfo {
+            eh_frame: section_data(".eh_frame"),
+            eh_frame_address: section_address(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            text_address: section_address(".text"),
+            endian,
+        }
+    }
+
+    /// Call frame information for no code at all
+    #[cfg(test)]
+    pub fn empty() -> CallFrameInfo {
+        CallFrameInfo {
+            eh_frame: Vec::new(),
+            eh_frame_address: 0,
+            debug_frame: Vec::new(),
+            text_address: 0,
+            endian: gimli::RunTimeEndian::Little,
+        }
+    }
+
+    /// Finds how to recover the caller's registers at `address`, preferring .eh_frame. CFA rules
+    /// given as DWARF expressions (as used by PLT stubs) are not supported.
+    pub fn frame_rule(&self, address: u64) -> Option<FrameRule> {
+        type EhFrame<'a> = gimli::EhFrame<gimli::EndianSlice<'a, gimli::RunTimeEndian>>;
+        type DebugFrame<'a> = gimli::DebugFrame<gimli::EndianSlice<'a, gimli::RunTimeEndian>>;
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_address)
+            .set_text(self.text_address);
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        if let Ok(row) =
+            eh_frame.unwind_info_for_address(&bases, &mut ctx, address, EhFrame::cie_from_offset)
+        {
+            return convert_unwind_row(&row);
+        }
+        let debug_frame = gimli::DebugFrame::new(&self.debug_frame, self.endian);
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        let row = debug_frame
+            .unwind_info_for_address(&bases, &mut ctx, address, DebugFrame::cie_from_offset)
+            .ok()?;
+        convert_unwind_row(&row)
+    }
+}
+
+fn convert_unwind_row<R: Reader>(row: &gimli::UnwindTableRow<R>) -> Option<FrameRule> {
+    let (cfa_register, cfa_offset) = match row.cfa() {
+        gimli::CfaRule::RegisterAndOffset { register, offset } => (register.0, *offset),
+        gimli::CfaRule::Expression(_) => return None,
+    };
+    let mut registers = Vec::new();
+    let mut has_return_address = false;
+    for (register, rule) in row.registers() {
+        has_return_address |= register.0 == RETURN_ADDRESS_REGISTER;
+        let saved = match rule {
+            gimli::RegisterRule::Undefined => SavedRegister::Undefined,
+            gimli::RegisterRule::SameValue => SavedRegister::SameValue,
+            gimli::RegisterRule::Offset(offset) => SavedRegister::AtCfaOffset(*offset),
+            gimli::RegisterRule::ValOffset(offset) => SavedRegister::CfaOffset(*offset),
+            gimli::RegisterRule::Register(other) => SavedRegister::InRegister(other.0),
+            _ => SavedRegister::Undefined,
+        };
+        registers.push((register.0, saved));
+    }
+    if !has_return_address {
+        registers.push((RETURN_ADDRESS_REGISTER, SavedRegister::Undefined));
+    }
+    Some(FrameRule {
+        cfa_register,
+        cfa_offset,
+        registers,
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -291,25 +779,190 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// Reads DW_AT_location, which is either a single expression or a location list. An expression
+/// that is just an address is a variable with a fixed place in memory.
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Option<Location>, Error> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
         let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
+        if let Ok(gimli::Operation::Address { address }) = gimli::Operation::parse(&mut pc, unit.encoding()) {
+            if pc.is_empty() {
+                return Ok(Some(Location::Address(address.try_into().unwrap())));
+            }
+        }
+        return Ok(Some(Location::Expression(to_expression(data, unit)?)));
+    }
+    let mut entries = match dwarf.attr_locations(unit, attr.value())? {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+    let mut list = Vec::new();
+    while let Some(entry) = entries.next()? {
+        list.push((
+            entry.range.begin.try_into().unwrap(),
+            entry.range.end.try_into().unwrap(),
+            to_expression(&entry.data, unit)?,
+        ));
+    }
+    Ok(Some(Location::List(list)))
+}
+
+fn to_expression<R: Reader>(data: &gimli::Expression<R>, unit: &gimli::Unit<R>) -> Result<Expression, Error> {
+    Ok(Expression {
+        bytecode: data.0.to_slice()?.to_vec(),
+        encoding: unit.encoding(),
+    })
+}
+
+/// Builds a DW_OP_implicit_value expression holding DW_AT_const_value
+fn const_value_location<R: Reader>(
+    value: &gimli::AttributeValue<R>,
+    size: usize,
+    unit: &gimli::Unit<R>,
+) -> Result<Option<Location>, Error> {
+    let bytes = match value {
+        gimli::AttributeValue::Block(data) => data.to_slice()?.to_vec(),
+        gimli::AttributeValue::Sdata(data) => data.to_le_bytes()[..size.min(8)].to_vec(),
+        _ => match value.udata_value() {
+            Some(data) => data.to_le_bytes()[..size.min(8)].to_vec(),
+            None => return Ok(None),
+        },
+    };
+    let mut bytecode = vec![gimli::DW_OP_implicit_value.0];
+    let mut len = bytes.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            bytecode.push(byte);
+            break;
+        }
+        bytecode.push(byte | 0x80);
+    }
+    bytecode.extend(bytes);
+    Ok(Some(Location::Expression(Expression {
+        bytecode,
+        encoding: unit.encoding(),
+    })))
+}
+
+/// Returns the DIE an abstract origin or specification attribute refers to
+fn get_origin<'unit, R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &'unit gimli::Unit<R>,
+) -> Option<gimli::DebuggingInformationEntry<'unit, 'unit, R>> {
+    match attr.value() {
+        gimli::AttributeValue::UnitRef(offset) => unit.entry(offset).ok(),
+        _ => None,
+    }
+}
+
+/// Evaluates a location expression in the frame described by `context`. `frame_base` is the
+/// enclosing function's DW_AT_frame_base, and `size` the size of the variable, which is needed
+/// to collect a value that is not in memory.
+pub fn evaluate_location(
+    expression: &Expression,
+    frame_base: Option<&Expression>,
+    size: usize,
+    load_bias: usize,
+    context: &dyn FrameContext,
+) -> Result<Place, String> {
+    let bytecode = gimli::EndianSlice::new(&expression.bytecode, gimli::RunTimeEndian::Little);
+    let mut evaluation = gimli::Evaluation::new(bytecode, expression.encoding);
+    let mut result = evaluation.evaluate();
+    loop {
+        let step = match result.map_err(|e| format!("Unhandled dwarf expression: {}", e))? {
+            gimli::EvaluationResult::Complete => break,
+            gimli::EvaluationResult::RequiresMemory { address, size, .. } => {
+                match context.read_memory(address as usize, size as usize) {
+                    Some(bytes) => {
+                        let mut word = [0; 8];
+                        word[..bytes.len()].copy_from_slice(&bytes);
+                        evaluation.resume_with_memory(gimli::Value::Generic(u64::from_le_bytes(word)))
                     }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
+                    None => return Err(format!("Cannot access memory at address {:#x}", address)),
+                }
+            }
+            gimli::EvaluationResult::RequiresRegister { register, .. } => {
+                match context.register(register.0) {
+                    Some(value) => evaluation.resume_with_register(gimli::Value::Generic(value)),
+                    None => return Ok(Place::OptimizedOut),
+                }
+            }
+            gimli::EvaluationResult::RequiresFrameBase => {
+                let frame_base = match frame_base {
+                    Some(frame_base) => frame_base,
+                    None => return Err("Could not find the frame base.".to_string()),
+                };
+                match evaluate_location(frame_base, None, 8, load_bias, context)? {
+                    Place::Memory(addr) => evaluation.resume_with_frame_base(addr as u64),
+                    Place::Value(bytes) => {
+                        let mut word = [0; 8];
+                        word[..bytes.len().min(8)].copy_from_slice(&bytes[..bytes.len().min(8)]);
+                        evaluation.resume_with_frame_base(u64::from_le_bytes(word))
                     }
-                    _ => {}
+                    Place::OptimizedOut => return Ok(Place::OptimizedOut),
                 }
             }
-        }
+            gimli::EvaluationResult::RequiresCallFrameCfa => match context.call_frame_cfa() {
+                Some(cfa) => evaluation.resume_with_call_frame_cfa(cfa as u64),
+                None => return Err("Could not compute the frame address.".to_string()),
+            },
+            gimli::EvaluationResult::RequiresRelocatedAddress(address) => {
+                evaluation.resume_with_relocated_address((address as usize).wrapping_add(load_bias) as u64)
+            }
+            // The value a parameter had on entry can only be recovered from the caller, which
+            // gdb does with call site information. We don't.
+            gimli::EvaluationResult::RequiresEntryValue(_) => return Ok(Place::OptimizedOut),
+            gimli::EvaluationResult::RequiresTls(_) => {
+                return Err("Cannot access thread-local variables.".to_string())
+            }
+            _ => return Err("Unhandled dwarf expression opcode".to_string()),
+        };
+        result = step;
+    }
+
+    let pieces = evaluation.result();
+    if let [gimli::Piece { location: gimli::Location::Address { address }, .. }] = pieces[..] {
+        return Ok(Place::Memory(address as usize));
+    }
+    let mut bytes = Vec::new();
+    for piece in pieces.iter() {
+        let piece_size = match piece.size_in_bits {
+            Some(bits) => bits as usize / 8 + (bits % 8 != 0) as usize,
+            None => size,
+        };
+        let mut piece_bytes = match piece.location {
+            gimli::Location::Empty => return Ok(Place::OptimizedOut),
+            gimli::Location::Register { register } => match context.register(register.0) {
+                Some(value) => value.to_le_bytes().to_vec(),
+                None => return Ok(Place::OptimizedOut),
+            },
+            gimli::Location::Address { address } => context
+                .read_memory(address as usize, piece_size)
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", address))?,
+            gimli::Location::Value { value } => value.to_u64(!0).unwrap_or(0).to_le_bytes().to_vec(),
+            gimli::Location::Bytes { value } => value.to_vec(),
+            gimli::Location::ImplicitPointer { .. } => {
+                return Err("Implicit pointers are not supported.".to_string())
+            }
+        };
+        piece_bytes.resize(piece_size, 0);
+        bytes.extend(piece_bytes);
     }
-    None
+    bytes.resize(size, 0);
+    Ok(Place::Value(bytes))
+}
+
+/// Formats an expression as its list of operations, like dwarfdump does
+pub fn format_expression(expression: &Expression) -> String {
+    let bytecode = gimli::EndianSlice::new(&expression.bytecode, gimli::RunTimeEndian::Little);
+    let mut text = String::new();
+    let _ = dump_exprloc(&mut text, expression.encoding, &gimli::Expression(bytecode));
+    text
 }
 
 // based on dwarf_dump.rs
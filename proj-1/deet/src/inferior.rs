@@ -1,14 +1,29 @@
+use nix::errno::Errno;
 use nix::sys::ptrace;
 use nix::sys::signal;
+use nix::sys::uio::{process_vm_readv, IoVec, RemoteIoVec};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use std::collections::HashMap;
-use std::process::Child;
 use std::process::Command;
 use std::os::unix::process::CommandExt;
 use std::mem::size_of;
+use std::sync::atomic::{AtomicI32, Ordering};
 
-use crate::dwarf_data::DwarfData;
+use crate::dwarf_data::{module_for_addr, read_unsigned, DwarfData, SavedRegister};
+
+/// Offset of `u_debugreg` in the `struct user` area that PTRACE_PEEKUSER/POKEUSER address
+const DEBUG_REGISTERS_OFFSET: usize = 848;
+
+/// The x86 debug registers DR0-DR3 hold one watched address each
+pub const WATCH_SLOTS: usize = 4;
+
+/// Which accesses a hardware watchpoint slot traps on. x86 cannot trap on reads alone.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchAccess {
+    Write,
+    ReadWrite,
+}
 
 /// for writing breakpionts
 fn align_addr_to_word(addr: usize) -> usize {
@@ -27,6 +42,120 @@ impl Breakpoint {
     }
 }
 
+/// Personality flag that disables address space randomization, from <sys/personality.h>
+const ADDR_NO_RANDOMIZE: libc::c_int = 0x0040000;
+
+/// Backtraces are cut off after this many frames, in case the stack is corrupt
+const MAX_FRAMES: usize = 256;
+
+/// DWARF register number of the return address column
+const RETURN_ADDRESS_REGISTER: u16 = 16;
+
+/// Maps the x86-64 DWARF register numbers 0-16 to user_regs_struct fields
+fn dwarf_register_field(regs: &mut libc::user_regs_struct, register: u16) -> Option<&mut u64> {
+    Some(match register {
+        0 => &mut regs.rax,
+        1 => &mut regs.rdx,
+        2 => &mut regs.rcx,
+        3 => &mut regs.rbx,
+        4 => &mut regs.rsi,
+        5 => &mut regs.rdi,
+        6 => &mut regs.rbp,
+        7 => &mut regs.rsp,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        15 => &mut regs.r15,
+        16 => &mut regs.rip,
+        _ => return None,
+    })
+}
+
+/// Reads a register by its DWARF register number
+pub fn dwarf_register(regs: &libc::user_regs_struct, register: u16) -> Option<u64> {
+    let mut regs = *regs;
+    dwarf_register_field(&mut regs, register).map(|field| *field)
+}
+
+fn set_dwarf_register(regs: &mut libc::user_regs_struct, register: u16, value: u64) {
+    if let Some(field) = dwarf_register_field(regs, register) {
+        *field = value;
+    }
+}
+
+/// The registers of one frame of the call stack. In the outer frames only %rip, %rsp and the
+/// callee-saved registers are meaningful.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub regs: libc::user_regs_struct,
+    /// True for the frame that is actually executing
+    pub innermost: bool,
+}
+
+impl Frame {
+    /// Returns the address to look up debug information with. In the outer frames %rip is a
+    /// return address, which may already belong to the next line or even the next function, so
+    /// the call instruction right before it is used instead.
+    pub fn pc(&self) -> usize {
+        if self.innermost {
+            self.regs.rip as usize
+        } else {
+            self.regs.rip as usize - 1
+        }
+    }
+}
+
+/// The registers shown by `info registers`, in the order gdb uses
+pub const REGISTER_NAMES: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+/// Maps a general purpose register's name as written after `$`, e.g. "rax" or "pc", to its
+/// user_regs_struct field
+fn register_field<'a>(regs: &'a mut libc::user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        "orig_rax" => &mut regs.orig_rax,
+        _ => return None,
+    })
+}
+
+/// Reads a general purpose register by its name as written after `$`
+pub fn register_value(regs: &libc::user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    register_field(&mut regs, name).map(|field| *field)
+}
+
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
     /// current instruction pointer that it is stopped at.
@@ -38,25 +167,423 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior replaced its program with execve. It is stopped at the start of
+    /// the new program, which has none of our breakpoints.
+    Execed,
+
+    /// Indicates the inferior stopped at an event the catch policy asks for. Contains the event
+    /// and the current instruction pointer.
+    Caught(CatchEvent, usize),
+}
+
+/// An event the inferior stopped at because of a catchpoint
+pub enum CatchEvent {
+    /// The thread is about to make the system call with the given number
+    SyscallEntry(u64),
+    /// The thread has returned from the system call with the given number
+    SyscallExit(u64),
+    /// The inferior forked the given child. The process that is followed stops.
+    Fork(Pid),
+    Vfork(Pid),
+}
+
+/// Which events stop the inferior besides breakpoints, watchpoints and signals, as set with
+/// `catch`
+#[derive(Clone, Default, PartialEq)]
+pub struct CatchPolicy {
+    /// The numbers of the system calls to stop at on entry and on exit, or every one if empty.
+    /// With None, the inferior is not resumed with PTRACE_SYSCALL at all.
+    pub syscalls: Option<Vec<u64>>,
+    /// Signals that stop the inferior even if their `handle` policy says otherwise
+    pub signals: Vec<signal::Signal>,
+    pub fork: bool,
+    pub vfork: bool,
+    /// Every system call is stopped at to be logged, but only while the inferior is continued:
+    /// stepping and finishing pass over those that no catchpoint is interested in
+    pub trace_syscalls: bool,
+}
+
+impl CatchPolicy {
+    /// Tells whether a catchpoint stops at the system call with this number
+    fn catches_syscall(&self, number: u64) -> bool {
+        match &self.syscalls {
+            Some(numbers) => numbers.is_empty() || numbers.contains(&number),
+            None => false,
+        }
+    }
+}
+
+/// What happens to the two processes when the inferior forks, as set with `set
+/// follow-fork-mode` and `set detach-on-fork`
+#[derive(Clone, Copy, PartialEq)]
+pub struct ForkPolicy {
+    /// Keep debugging the child instead of the parent
+    pub follow_child: bool,
+    /// Let the other process run untraced, instead of holding it stopped until the followed
+    /// one is gone. The child of a vfork is always let go, since its parent cannot run before.
+    pub detach: bool,
+}
+
+impl Default for ForkPolicy {
+    fn default() -> ForkPolicy {
+        ForkPolicy {
+            follow_child: false,
+            detach: true,
+        }
+    }
+}
+
+/// How a signal the inferior receives is treated, as set with `handle`
+#[derive(Clone, Copy, PartialEq)]
+pub struct SignalPolicy {
+    /// Give control back to the user
+    pub stop: bool,
+    /// Announce the signal
+    pub print: bool,
+    /// Deliver the signal to the inferior when it resumes
+    pub pass: bool,
+}
+
+impl SignalPolicy {
+    /// gdb's defaults: signals that programs use for routine notifications are passed on
+    /// silently, and the ones the debugger uses itself are not passed on. Unlike gdb, SIGSTOP
+    /// is not passed on either, since delivering it would just stop the inferior again.
+    pub fn default_for(sig: signal::Signal) -> SignalPolicy {
+        use signal::Signal::*;
+        match sig {
+            SIGALRM | SIGURG | SIGCHLD | SIGIO | SIGVTALRM | SIGPROF | SIGWINCH => SignalPolicy {
+                stop: false,
+                print: false,
+                pass: true,
+            },
+            SIGINT | SIGTRAP | SIGSTOP => SignalPolicy {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            _ => SignalPolicy {
+                stop: true,
+                print: true,
+                pass: true,
+            },
+        }
+    }
+}
+
+/// Describes a signal the way strsignal does
+pub fn signal_description(sig: signal::Signal) -> &'static str {
+    use signal::Signal::*;
+    match sig {
+        SIGHUP => "Hangup",
+        SIGINT => "Interrupt",
+        SIGQUIT => "Quit",
+        SIGILL => "Illegal instruction",
+        SIGTRAP => "Trace/breakpoint trap",
+        SIGABRT => "Aborted",
+        SIGBUS => "Bus error",
+        SIGFPE => "Floating point exception",
+        SIGKILL => "Killed",
+        SIGUSR1 => "User defined signal 1",
+        SIGSEGV => "Segmentation fault",
+        SIGUSR2 => "User defined signal 2",
+        SIGPIPE => "Broken pipe",
+        SIGALRM => "Alarm clock",
+        SIGTERM => "Terminated",
+        SIGSTKFLT => "Stack fault",
+        SIGCHLD => "Child exited",
+        SIGCONT => "Continued",
+        SIGSTOP => "Stopped (signal)",
+        SIGTSTP => "Stopped",
+        SIGTTIN => "Stopped (tty input)",
+        SIGTTOU => "Stopped (tty output)",
+        SIGURG => "Urgent I/O condition",
+        SIGXCPU => "CPU time limit exceeded",
+        SIGXFSZ => "File size limit exceeded",
+        SIGVTALRM => "Virtual timer expired",
+        SIGPROF => "Profiling timer expired",
+        SIGWINCH => "Window changed",
+        SIGIO => "I/O possible",
+        SIGPWR => "Power failure",
+        SIGSYS => "Bad system call",
+    }
+}
+
+/// The process ctrl+c is forwarded to, or 0 if there is none. Processes we start share our
+/// process group, so the terminal interrupts them by itself; the ones we attached to do not.
+static INTERRUPT_TARGET: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_interrupt(_: libc::c_int) {
+    let pid = INTERRUPT_TARGET.load(Ordering::SeqCst);
+    if pid != 0 {
+        unsafe {
+            libc::kill(pid, libc::SIGINT);
+        }
+    }
+}
+
+/// Makes ctrl+c stop the inferior instead of the debugger. The SIGINT reaches us while we wait
+/// for the inferior, which then reports the signal as a stop like any other. Unlike ignoring
+/// SIGINT, a handler is reset by exec, so the programs we start can still be interrupted.
+pub fn install_interrupt_handler() -> Result<(), nix::Error> {
+    let action = signal::SigAction::new(
+        signal::SigHandler::Handler(forward_interrupt),
+        signal::SaFlags::SA_RESTART,
+        signal::SigSet::empty(),
+    );
+    unsafe { signal::sigaction(signal::Signal::SIGINT, &action) }.map(|_| ())
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
+    // Like gdb, turn off address space randomization, so that the program and its libraries are
+    // loaded at the same addresses on every run and breakpoints in them stay valid
+    unsafe {
+        let persona = libc::personality(0xffffffff);
+        if persona != -1 {
+            libc::personality((persona | ADDR_NO_RANDOMIZE) as libc::c_ulong);
+        }
+    }
     ptrace::traceme().or(Err(std::io::Error::new(
         std::io::ErrorKind::Other,
         "ptrace TRACEME failed",
     )))
 }
 
+/// Read access to the registers and memory of a stopped program. This is either a live
+/// Inferior or, after a crash, its core dump.
+pub trait ProcessState {
+    fn getregs(&self) -> Result<libc::user_regs_struct, nix::Error>;
+
+    fn getfpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error>;
+
+    /// Reads `len` bytes starting at `addr`, showing the original bytes in place of our 0xcc
+    fn read_memory(&self, addr: usize, len: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Vec<u8>, nix::Error>;
+
+    /// Reads a word from the program's memory
+    fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        let bytes = self.read_memory(addr, size_of::<usize>(), &HashMap::new())?;
+        Ok(read_unsigned(&bytes) as usize)
+    }
+
+    /// Returns the frame that is currently executing
+    fn innermost_frame(&self) -> Result<Frame, nix::Error> {
+        Ok(Frame {
+            regs: self.getregs()?,
+            innermost: true,
+        })
+    }
+
+    /// Computes the canonical frame address of a frame, i.e. the value of %rsp before the call
+    /// instruction. This is the frame base that DW_OP_fbreg offsets are relative to, and the
+    /// return address is stored right below it. Without call frame information, the prologue
+    /// is inspected instead.
+    fn frame_base(&self, frame: &Frame, dwarf_data: &DwarfData, breakpoints: &HashMap<usize, Breakpoint>) -> Result<usize, nix::Error> {
+        let regs = frame.regs;
+        if let Some(rule) = dwarf_data.get_frame_rule(frame.pc()) {
+            if let Some(value) = dwarf_register(&regs, rule.cfa_register) {
+                return Ok((value as i64 + rule.cfa_offset) as usize);
+            }
+        }
+        let rip = regs.rip as usize;
+        let rsp = regs.rsp as usize;
+        let rbp = regs.rbp as usize;
+        // Where the frame starts depends on how far we are into the prologue
+        // (push %rbp; mov %rsp,%rbp)
+        let push_addr = match dwarf_data.get_function_containing_addr(rip) {
+            Some(func) => {
+                let code = self.read_memory(func.address, 16, breakpoints)?;
+                code.windows(4)
+                    .position(|insn| insn == [0x55, 0x48, 0x89, 0xe5])
+                    .map(|offset| func.address + offset)
+            }
+            None => None,
+        };
+        Ok(match push_addr {
+            Some(push_addr) if rip <= push_addr => rsp + 8,
+            Some(push_addr) if rip < push_addr + 4 => rsp + 16,
+            _ => rbp + 16,
+        })
+    }
+
+    /// Unwinds the call stack with the call frame information and returns every frame,
+    /// innermost first. `modules` are the executable and the shared libraries, whose call frame
+    /// information is used for the code they contain. Unwinding stops at `_start`, at code
+    /// without call frame information, and wherever the return address is undefined.
+    fn unwind_stack(&self, modules: &[&DwarfData]) -> Result<Vec<Frame>, nix::Error> {
+        let mut frames = vec![self.innermost_frame()?];
+        while frames.len() < MAX_FRAMES {
+            let frame = *frames.last().unwrap();
+            let regs = frame.regs;
+            let dwarf_data = module_for_addr(modules, frame.pc());
+            if dwarf_data.get_symbol_from_addr(regs.rip as usize) == Some("_start") {
+                break;
+            }
+            let rule = match dwarf_data.get_frame_rule(frame.pc()) {
+                Some(rule) => rule,
+                None => break,
+            };
+            let cfa = match dwarf_register(&regs, rule.cfa_register) {
+                Some(value) => (value as i64 + rule.cfa_offset) as u64,
+                None => break,
+            };
+            let mut caller = regs;
+            let mut return_address = None;
+            for (register, saved) in rule.registers {
+                let value = match saved {
+                    SavedRegister::Undefined => None,
+                    SavedRegister::SameValue => dwarf_register(&regs, register),
+                    SavedRegister::AtCfaOffset(offset) => {
+                        self.read_word((cfa as i64 + offset) as usize).ok().map(|word| word as u64)
+                    }
+                    SavedRegister::CfaOffset(offset) => Some((cfa as i64 + offset) as u64),
+                    SavedRegister::InRegister(other) => dwarf_register(&regs, other),
+                };
+                if register == RETURN_ADDRESS_REGISTER {
+                    return_address = value;
+                } else if let Some(value) = value {
+                    set_dwarf_register(&mut caller, register, value);
+                }
+            }
+            caller.rsp = cfa;
+            caller.rip = match return_address {
+                Some(return_address) if return_address != 0 => return_address,
+                _ => break,
+            };
+            // The stack grows down, so the caller's frame must be above ours
+            if caller.rsp <= regs.rsp {
+                break;
+            }
+            frames.push(Frame {
+                regs: caller,
+                innermost: false,
+            });
+        }
+        Ok(frames)
+    }
+}
+
+/// A thread of the inferior. Threads are numbered from 1 in the order they appear, like gdb
+/// does, and the numbers are never reused.
+struct Thread {
+    number: usize,
+    tid: Pid,
+    /// Signal to deliver when the thread is resumed next
+    pending_signal: Option<signal::Signal>,
+    /// A stop that happened while the thread was being stopped for another thread's sake. It is
+    /// reported the next time the inferior is resumed, instead of resuming it.
+    pending_stop: Option<Status>,
+    /// Whether the thread's next syscall stop is the exit of a system call rather than the entry
+    /// of one. Syscall stops look the same either way.
+    in_syscall: bool,
+    /// The system call the thread last stopped in
+    syscall: u64,
+    /// Where the thread was stopped when the user selected another one. If it is still there
+    /// on a breakpoint when resumed, it steps over the breakpoint rather than reporting it again.
+    stopped_at: Option<usize>,
+    /// Whether a SIGSTOP stop_threads sent is still to arrive, to be ignored when it does
+    stray_sigstop: bool,
+}
+
+impl Thread {
+    fn new(number: usize, tid: Pid) -> Thread {
+        Thread {
+            number,
+            tid,
+            pending_signal: None,
+            pending_stop: None,
+            in_syscall: false,
+            syscall: 0,
+            stopped_at: None,
+            stray_sigstop: false,
+        }
+    }
+}
+
+/// Sends a signal to a single thread of a process
+fn tgkill(pid: Pid, tid: Pid, sig: signal::Signal) -> Result<(), nix::Error> {
+    let res = unsafe { libc::syscall(libc::SYS_tgkill, pid.as_raw(), tid.as_raw(), sig as libc::c_int) };
+    nix::errno::Errno::result(res).map(drop)
+}
+
+/// Lists the threads of a process in /proc/<pid>/task, the main thread included
+fn list_tasks(pid: Pid) -> Vec<Pid> {
+    let mut tasks: Vec<Pid> = match std::fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .map(Pid::from_raw)
+            .collect(),
+        Err(_) => vec![pid],
+    };
+    tasks.sort_by_key(|tid| tid.as_raw());
+    tasks
+}
+
+/// The ptrace options of every thread we trace: we hear about new threads and processes, and
+/// about exec. Syscall stops are told apart from SIGTRAPs.
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+        | ptrace::Options::PTRACE_O_TRACESYSGOOD
+}
+
 pub struct Inferior {
-    child: Child,
+    /// Whether we attached to a process that somebody else started
+    attached: bool,
+    pid: Pid,
+    /// Every thread we trace, the main thread first. The inferior runs in all-stop mode: either
+    /// all of them are stopped, or all of them are running.
+    threads: Vec<Thread>,
+    next_thread_number: usize,
+    /// The thread whose registers are read and written, and which is single-stepped
+    current: Pid,
+    /// The thread the user last saw stop or selected
+    reported: Pid,
+    /// Threads whose initial stop arrived before their creator reported the clone
+    early_threads: Vec<Pid>,
+    signal_policies: HashMap<signal::Signal, SignalPolicy>,
+    fork_policy: ForkPolicy,
+    catch_policy: CatchPolicy,
+    /// Processes a fork left stopped because `detach-on-fork` is off, each as its thread ids,
+    /// main thread first. None of our breakpoints are installed in them.
+    held: Vec<Vec<Pid>>,
+    /// The threads of the parent of a vfork we followed into the child. The parent is let go
+    /// once the child stops sharing its memory by calling exec or exiting.
+    vfork_parent: Vec<Pid>,
+    /// What the debug registers of every thread are programmed with
+    watch_slots: Vec<(usize, usize, WatchAccess)>,
 }
 
 impl Inferior {
+    fn with_pid(attached: bool, pid: Pid) -> Inferior {
+        if attached {
+            INTERRUPT_TARGET.store(pid.as_raw(), Ordering::SeqCst);
+        }
+        Inferior {
+            attached,
+            pid,
+            threads: vec![Thread::new(1, pid)],
+            next_thread_number: 2,
+            current: pid,
+            reported: pid,
+            early_threads: Vec::new(),
+            signal_policies: HashMap::new(),
+            fork_policy: ForkPolicy::default(),
+            catch_policy: CatchPolicy::default(),
+            held: Vec::new(),
+            vfork_parent: Vec::new(),
+            watch_slots: Vec::new(),
+        }
+    }
+
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: &Vec<usize>, bp_map: &mut HashMap<usize, Breakpoint>) -> Option<Inferior> {
+    /// an error is encountered. The process is left stopped right after the exec, without any
+    /// breakpoints installed.
+    pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
         let mut command = Command::new(target);
         command.args(args);
         unsafe {
@@ -64,23 +591,8 @@ impl Inferior {
         }
         let child = command.spawn().expect("Failed to spawn a subprocess");
         let pid = nix::unistd::Pid::from_raw(child.id() as i32);
-        let mut infer = Inferior{child};
-        // install breakpoints
-        for i in 0..breakpoints.len() {
-            let addr = &breakpoints[i];
-            match infer.write_byte(*addr, 0xcc as u8) {
-                Ok(orig_byte) => {
-                    let bp = bp_map.get_mut(&*addr).unwrap();
-                    if orig_byte != 0xcc { // avoid same breakpoint bug
-                        bp.orig_byte = orig_byte;
-                    }
-                }
-                Err(e) => {
-                    println!("Fail to install Breakpoint {} at {:#x}: {}", i + 1, addr, e);
-                }
-            }
-        }
-        // check SIGTRAP
+        let infer = Inferior::with_pid(false, pid);
+        // check SIGTRAP. The child must be stopped before we can poke at its memory
         match waitpid(pid, None).ok()? {
             WaitStatus::Stopped(_, _) => {
                 // println!("#{} got {}", _pid, signal); // it works
@@ -90,48 +602,521 @@ impl Inferior {
                 return None
             },
         }
+        // Threads and processes the program creates are traced as well
+        ptrace::setoptions(pid, trace_options()).ok()?;
+        Some(infer)
+    }
+
+    /// Attaches to every thread of a running process with PTRACE_ATTACH and waits until they
+    /// have stopped. Returns None if the process cannot be traced. No breakpoints are installed
+    /// yet.
+    pub fn attach(pid: Pid) -> Option<Inferior> {
+        if let Err(e) = ptrace::attach(pid) {
+            println!("Could not attach to process {}: {}", pid, e);
+            return None;
+        }
+        let mut infer = Inferior::with_pid(true, pid);
+        match waitpid(pid, Some(WaitPidFlag::__WALL)).ok()? {
+            WaitStatus::Stopped(_, _) => {}
+            other => {
+                println!("Process {} did not stop. Got {:?}", pid, other);
+                return None
+            }
+        }
+        ptrace::setoptions(pid, trace_options()).ok()?;
+        // Threads created from now on are reported by the ones we already trace
+        for tid in list_tasks(pid) {
+            if tid == pid || ptrace::attach(tid).is_err() {
+                continue;
+            }
+            if let Ok(WaitStatus::Stopped(_, _)) = waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                if ptrace::setoptions(tid, trace_options()).is_ok() {
+                    infer.add_thread(tid);
+                }
+            }
+        }
         Some(infer)
     }
 
+    /// Writes 0xcc to every breakpoint address and remembers the bytes it replaced. Breakpoints
+    /// in code that is not mapped, such as a library that has not been loaded yet, are left
+    /// pending; this may be called again once it is.
+    pub fn install_breakpoints(&mut self, bp_map: &mut HashMap<usize, Breakpoint>) {
+        for (addr, bp) in bp_map.iter_mut() {
+            match self.write_byte(*addr, 0xcc) {
+                Ok(orig_byte) => {
+                    if orig_byte != 0xcc { // avoid same breakpoint bug
+                        bp.orig_byte = orig_byte;
+                    }
+                }
+                Err(nix::Error::Sys(Errno::EIO)) | Err(nix::Error::Sys(Errno::EFAULT)) => {}
+                Err(e) => {
+                    println!("Fail to install breakpoint at {:#x}: {}", addr, e);
+                }
+            }
+        }
+    }
+
+    /// Removes all breakpoints and watchpoints and lets the process run on without us
+    pub fn detach(&mut self, bp_map: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        for (addr, bp) in bp_map.iter() {
+            match self.write_byte(*addr, bp.orig_byte) {
+                // A pending breakpoint, which was never installed
+                Ok(_) | Err(nix::Error::Sys(Errno::EIO)) | Err(nix::Error::Sys(Errno::EFAULT)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.set_watch_slots(&[])?;
+        let stray_sigstop = self.threads.iter().any(|thread| thread.stray_sigstop);
+        for thread in self.threads.iter_mut() {
+            ptrace::detach(thread.tid, thread.pending_signal.take())?;
+        }
+        // Undo the SIGSTOP stop_threads sent but never saw arrive
+        if stray_sigstop {
+            signal::kill(self.pid, signal::Signal::SIGCONT)?;
+        }
+        for tid in self.held.drain(..).flatten() {
+            ptrace::detach(tid, None)?;
+        }
+        Ok(())
+    }
+
+    /// Sets how the signals the inferior receives are treated. Signals without a policy are
+    /// treated as SignalPolicy::default_for says.
+    pub fn set_signal_policies(&mut self, policies: &HashMap<signal::Signal, SignalPolicy>) {
+        self.signal_policies = policies.clone();
+    }
+
+    /// Sets which process is debugged after a fork, and what becomes of the other one
+    pub fn set_fork_policy(&mut self, policy: ForkPolicy) {
+        self.fork_policy = policy;
+    }
+
+    /// Sets which system calls, signals and forks stop the inferior. Takes effect the next time
+    /// it is resumed.
+    pub fn set_catch_policy(&mut self, policy: CatchPolicy) {
+        self.catch_policy = policy;
+    }
+
+    /// Chooses the signal delivered to the current thread when it resumes next, replacing the
+    /// one it stopped with. None resumes it without a signal.
+    pub fn set_pending_signal(&mut self, sig: Option<signal::Signal>) {
+        let current = self.current;
+        self.thread_mut(current).pending_signal = sig;
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Tells whether we attached to this process instead of starting it
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Takes over the first process held after a fork, along with the ones held after it. Its
+    /// threads are left stopped where the fork left them, without breakpoints.
+    pub fn take_held(&mut self) -> Option<Inferior> {
+        if self.held.is_empty() {
+            return None;
+        }
+        let tids = self.held.remove(0);
+        let mut infer = Inferior::with_pid(self.attached, tids[0]);
+        for tid in &tids[1..] {
+            infer.add_thread(*tid);
+        }
+        infer.signal_policies = self.signal_policies.clone();
+        infer.fork_policy = self.fork_policy;
+        infer.catch_policy = self.catch_policy.clone();
+        infer.held = std::mem::take(&mut self.held);
+        Some(infer)
+    }
+
+    /// Returns the number and thread id of every thread, in the order they appeared
+    pub fn threads(&self) -> Vec<(usize, Pid)> {
+        self.threads.iter().map(|thread| (thread.number, thread.tid)).collect()
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Returns the number and thread id of the thread whose registers are used
+    pub fn current_thread(&self) -> (usize, Pid) {
+        let thread = self.threads.iter().find(|thread| thread.tid == self.current).unwrap();
+        (thread.number, thread.tid)
+    }
+
+    /// Makes the thread with the given number the current one. Returns false if there is no
+    /// such thread.
+    pub fn select_thread(&mut self, number: usize) -> bool {
+        let tid = match self.threads.iter().find(|thread| thread.number == number) {
+            Some(thread) => thread.tid,
+            None => return false,
+        };
+        if tid != self.current {
+            let previous = self.current;
+            let rip = ptrace::getregs(previous).ok().map(|regs| regs.rip as usize);
+            self.thread_mut(previous).stopped_at = rip;
+        }
+        self.current = tid;
+        self.reported = tid;
+        true
+    }
+
+    /// Returns the number and thread id of the current thread if a stop switched to it since
+    /// the user last saw it, and remembers that they have now
+    pub fn take_thread_switch(&mut self) -> Option<(usize, Pid)> {
+        if self.current == self.reported {
+            return None;
+        }
+        self.reported = self.current;
+        Some(self.current_thread())
+    }
+
+    fn is_thread(&self, tid: Pid) -> bool {
+        self.threads.iter().any(|thread| thread.tid == tid)
+    }
+
+    fn thread_mut(&mut self, tid: Pid) -> &mut Thread {
+        self.threads.iter_mut().find(|thread| thread.tid == tid).unwrap()
+    }
+
+    /// Starts tracking a new thread and programs its debug registers, which it does not inherit
+    fn add_thread(&mut self, tid: Pid) -> usize {
+        let number = self.next_thread_number;
+        self.next_thread_number += 1;
+        self.threads.push(Thread::new(number, tid));
+        if let Err(e) = self.program_watch_slots(tid) {
+            println!("Cannot set watchpoints in thread {}: {}", number, e);
+        }
+        number
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(index) = self.threads.iter().position(|thread| thread.tid == tid) {
+            let thread = self.threads.remove(index);
+            println!("[Thread {} (LWP {}) exited]", thread.number, tid);
+        }
+        if self.current == tid {
+            self.current = self.threads[0].tid;
+        }
+    }
+
+    /// Waits for the next event of the thread `tid`, or of any thread if `tid` is None, and
+    /// returns the thread it happened to along with its Status. The main thread exiting ends
+    /// the process. Threads being created or exiting are kept track of on the way, and None is
+    /// returned for such events. A thread that reported creating another one is resumed,
+    /// single-stepped if `single_step` is set; the new thread is left stopped unless all
+    /// threads are running, i.e. `tid` is None. Forks are dealt with as the fork policy says.
+    fn wait_event(&mut self, tid: Option<Pid>, single_step: bool, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<(Pid, Status)>, nix::Error> {
+        let status = waitpid(tid.unwrap_or_else(|| Pid::from_raw(-1)), Some(WaitPidFlag::__WALL))?;
+        Ok(match status {
+            WaitStatus::Exited(tid, exit_code) if tid == self.pid => {
+                self.release_vfork_parent(breakpoints)?;
+                Some((tid, Status::Exited(exit_code)))
+            }
+            WaitStatus::Signaled(tid, signal, _core_dumped) if tid == self.pid => {
+                self.release_vfork_parent(breakpoints)?;
+                Some((tid, Status::Signaled(signal)))
+            }
+            WaitStatus::Exited(tid, _) | WaitStatus::Signaled(tid, _, _) => {
+                self.remove_thread(tid);
+                None
+            }
+            // A new thread can be seen stopping before its creator reports it
+            WaitStatus::Stopped(tid, signal) if !self.is_thread(tid) => {
+                if signal == signal::Signal::SIGSTOP {
+                    self.early_threads.push(tid);
+                }
+                None
+            }
+            WaitStatus::Stopped(tid, signal::Signal::SIGSTOP) if self.thread_mut(tid).stray_sigstop => {
+                self.thread_mut(tid).stray_sigstop = false;
+                if single_step {
+                    self.step_thread(tid)?;
+                } else {
+                    self.resume(tid, None)?;
+                }
+                None
+            }
+            WaitStatus::Stopped(tid, signal) => {
+                let regs = ptrace::getregs(tid)?;
+                Some((tid, Status::Stopped(signal, regs.rip as usize)))
+            }
+            WaitStatus::PtraceSyscall(tid) => {
+                let regs = ptrace::getregs(tid)?;
+                let thread = self.thread_mut(tid);
+                thread.in_syscall = !thread.in_syscall;
+                // rt_sigreturn restores orig_rax along with the other registers, to -1
+                let number = match regs.orig_rax {
+                    number if thread.in_syscall || number != u64::MAX => number,
+                    _ => thread.syscall,
+                };
+                thread.syscall = number;
+                let event = if thread.in_syscall {
+                    CatchEvent::SyscallEntry(number)
+                } else {
+                    CatchEvent::SyscallExit(number)
+                };
+                if self.catch_policy.trace_syscalls || self.catch_policy.catches_syscall(number) {
+                    Some((tid, Status::Caught(event, regs.rip as usize)))
+                } else {
+                    self.resume(tid, None)?;
+                    None
+                }
+            }
+            WaitStatus::PtraceEvent(creator, _, libc::PTRACE_EVENT_CLONE) => {
+                // Events are reported from within the system call that caused them
+                self.thread_mut(creator).in_syscall = true;
+                let new_tid = Pid::from_raw(ptrace::getevent(creator)? as i32);
+                match self.early_threads.iter().position(|early| *early == new_tid) {
+                    Some(index) => {
+                        self.early_threads.remove(index);
+                    }
+                    None => {
+                        waitpid(new_tid, Some(WaitPidFlag::__WALL))?;
+                    }
+                }
+                let number = self.add_thread(new_tid);
+                println!("[New thread {} (LWP {})]", number, new_tid);
+                if tid.is_none() {
+                    self.resume(new_tid, None)?;
+                }
+                if single_step {
+                    self.step_thread(creator)?;
+                } else {
+                    self.resume(creator, None)?;
+                }
+                None
+            }
+            WaitStatus::PtraceEvent(creator, _, event)
+                if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK =>
+            {
+                self.thread_mut(creator).in_syscall = true;
+                let child = Pid::from_raw(ptrace::getevent(creator)? as i32);
+                let vfork = event == libc::PTRACE_EVENT_VFORK;
+                match self.follow_fork(creator, child, vfork, tid.is_none(), single_step, breakpoints)? {
+                    Some(stopped) => {
+                        let event = if vfork { CatchEvent::Vfork(child) } else { CatchEvent::Fork(child) };
+                        let rip = ptrace::getregs(stopped)?.rip as usize;
+                        Some((stopped, Status::Caught(event, rip)))
+                    }
+                    None => None,
+                }
+            }
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                // Only the main thread is left, whichever thread called exec
+                self.release_vfork_parent(breakpoints)?;
+                let pid = self.pid;
+                self.threads.retain(|thread| thread.tid == pid);
+                self.thread_mut(pid).in_syscall = true;
+                self.current = pid;
+                self.early_threads.clear();
+                // The debug registers are cleared as well
+                self.watch_slots.clear();
+                Some((pid, Status::Execed))
             }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 
     /// Restart the program after being stopped.
-    pub fn cont_exec(&self) -> Result<Status, nix::Error> {
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    /// If %rip sits on a breakpoint, the original instruction is executed first, with the other
+    /// threads still stopped so that none of them can run past the breakpoint meanwhile. If the
+    /// program then traps on a breakpoint, %rip is rewound to the breakpoint address.
+    /// Threads the user switched away from step over the breakpoint they stopped at as well.
+    pub fn cont_exec(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        let selected = self.current;
+        let others: Vec<(Pid, usize)> = self
+            .threads
+            .iter_mut()
+            .filter_map(|thread| thread.stopped_at.take().map(|stopped_at| (thread.tid, stopped_at)))
+            .filter(|(tid, _)| *tid != selected)
+            .collect();
+        for (tid, stopped_at) in others {
+            if ptrace::getregs(tid)?.rip as usize != stopped_at {
+                continue;
+            }
+            self.current = tid;
+            match self.step_over_breakpoint(breakpoints)? {
+                Some(status @ Status::Stopped(signal::Signal::SIGTRAP, _)) if self.watch_hits()? != 0 => {
+                    return Ok(status)
+                }
+                Some(Status::Stopped(signal::Signal::SIGTRAP, _)) | None => {}
+                Some(other) => return Ok(other),
+            }
+        }
+        self.current = selected;
+        match self.step_over_breakpoint(breakpoints)? {
+            Some(status @ Status::Stopped(signal::Signal::SIGTRAP, _)) if self.watch_hits()? != 0 => {
+                return Ok(status)
+            }
+            Some(Status::Stopped(signal::Signal::SIGTRAP, _)) | None => {}
+            Some(other) => return Ok(other),
+        }
+        match self.resume_all(breakpoints)? {
+            // A watchpoint traps after the accessing instruction, which may happen to follow a
+            // breakpoint, so only rewind if no watchpoint fired
+            Status::Stopped(signal::Signal::SIGTRAP, rip)
+                if breakpoints.contains_key(&(rip - 1)) && self.watch_hits()? == 0 =>
+            {
+                self.set_register("rip", (rip - 1) as u64)?;
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1))
+            }
+            other => Ok(other),
+        }
+    }
+
+    /// If %rip points at one of our breakpoints, executes the original instruction and puts the
+    /// 0xcc back afterwards. Returns None if there was no breakpoint to step over.
+    fn step_over_breakpoint(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<Status>, nix::Error> {
+        let rip = self.getregs()?.rip as usize;
+        let bp = match breakpoints.get(&rip) {
+            Some(bp) => bp,
+            None => return Ok(None),
+        };
+        self.write_byte(rip, bp.orig_byte)?;
+        let status = self.step(breakpoints)?;
+        if let Status::Stopped(_, _) = status {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(Some(status))
+    }
+
+    /// Executes exactly one instruction, even if it is covered by a breakpoint.
+    pub fn step_instruction(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        match self.step_over_breakpoint(breakpoints)? {
+            Some(status) => Ok(status),
+            None => self.step(breakpoints),
+        }
+    }
+
+    /// Resumes the inferior until the current thread reaches `addr`. A temporary breakpoint is
+    /// used unless one of ours is already installed there; other threads pass it silently, and
+    /// so do system calls that are only being traced.
+    pub fn run_until(&mut self, addr: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        if breakpoints.contains_key(&addr) {
+            return self.cont_exec(breakpoints);
+        }
+        let thread = self.current;
+        let mut with_temporary = breakpoints.clone();
+        let orig_byte = self.write_byte(addr, 0xcc)?;
+        with_temporary.insert(addr, Breakpoint::new(addr, orig_byte).unwrap());
+        loop {
+            let status = self.cont_exec(&with_temporary)?;
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip == addr && self.current != thread && self.watch_hits()? == 0 => {}
+                Status::Caught(CatchEvent::SyscallEntry(number), _) | Status::Caught(CatchEvent::SyscallExit(number), _)
+                    if !self.catch_policy.catches_syscall(number) => {}
+                Status::Stopped(_, _) | Status::Caught(_, _) => {
+                    self.write_byte(addr, orig_byte)?;
+                    return Ok(status);
+                }
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Resumes the inferior until the call whose frame ends at `frame_end` returns to
+    /// `return_addr`. Recursive invocations returning to the same address are skipped.
+    fn run_until_return(&mut self, return_addr: usize, frame_end: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        loop {
+            let status = self.run_until(return_addr, breakpoints)?;
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == return_addr => {
+                    if self.getregs()?.rsp as usize >= frame_end {
+                        return Ok(status);
+                    }
+                }
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Steps to the beginning of the next source line. Calls into functions with debug
+    /// information are followed when `step_into` is set; any other call is run to completion.
+    /// Without line information for the current location, the inferior is simply continued.
+    pub fn step_line(&mut self, dwarf_data: &DwarfData, breakpoints: &HashMap<usize, Breakpoint>, step_into: bool) -> Result<Status, nix::Error> {
+        let mut regs = self.getregs()?;
+        let mut line = match dwarf_data.get_line_from_addr(regs.rip as usize) {
+            Some(line) => line,
+            None => return self.cont_exec(breakpoints),
+        };
+        let mut func_addr = dwarf_data.get_function_containing_addr(regs.rip as usize).map(|f| f.address);
+        loop {
+            let mut status = self.step_instruction(breakpoints)?;
+            let mut rip = match status {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => rip,
+                other => return Ok(other),
+            };
+            if breakpoints.contains_key(&rip) || self.watch_hits()? != 0 {
+                return Ok(status);
+            }
+            let mut new_regs = self.getregs()?;
+            let old_rip = regs.rip as usize;
+            // A call pushes the address of the following instruction and jumps away
+            if new_regs.rsp + 8 == regs.rsp && !(old_rip < rip && rip <= old_rip + 16) {
+                let return_addr = self.read_word(new_regs.rsp as usize)?;
+                if old_rip < return_addr && return_addr <= old_rip + 16 {
+                    if step_into {
+                        if let Some(callee) = dwarf_data.get_function_containing_addr(rip) {
+                            return self.run_until(dwarf_data.get_addr_after_prologue(callee), breakpoints);
+                        }
+                    }
+                    status = self.run_until_return(return_addr, regs.rsp as usize, breakpoints)?;
+                    rip = match status {
+                        Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == return_addr => rip,
+                        other => return Ok(other),
+                    };
+                    new_regs = self.getregs()?;
+                }
+            }
+            let new_func_addr = dwarf_data.get_function_containing_addr(rip).map(|f| f.address);
+            if new_func_addr != func_addr {
+                // We returned (or jumped) into another function, most likely into the middle of a
+                // line of the caller. Keep going until a new line of the caller starts.
+                line = match dwarf_data.get_line_from_addr(rip) {
+                    Some(line) => line,
+                    None => return Ok(status),
+                };
+                func_addr = new_func_addr;
+            } else if dwarf_data.is_line_start(rip) {
+                if let Some(new_line) = dwarf_data.get_line_from_addr(rip) {
+                    if new_line.file != line.file || new_line.number != line.number {
+                        return Ok(status);
+                    }
+                }
+            }
+            regs = new_regs;
+        }
+    }
+
+    /// Runs until the function containing %rip returns to its caller.
+    pub fn finish(&mut self, dwarf_data: &DwarfData, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        let frame_end = self.frame_base(&self.innermost_frame()?, dwarf_data, breakpoints)?;
+        let return_addr = self.read_word(frame_end - 8)?;
+        self.run_until_return(return_addr, frame_end, breakpoints)
     }
 
     /// Kill the existed process
     /// I decide to ignore the error in it
     pub fn kill(&mut self) {
-        match self.child.kill() {
+        // Processes held after a fork go as well
+        for held in self.held.drain(..) {
+            let _ = signal::kill(held[0], signal::Signal::SIGKILL);
+        }
+        match signal::kill(self.pid(), signal::Signal::SIGKILL) {
             Ok(()) => {
                 println!("Killing running inferior (pid {})", self.pid());
-                // reap
-                let rst = self.wait(None).unwrap();
-                match rst {
-                    Status::Signaled(_) => { // SIGKILL
-                        // nothing
-                    }
-                    _ => {
-                        println!("Error in killing.")
+                // reap every thread; the main thread is reported last
+                loop {
+                    match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL)) {
+                        Ok(WaitStatus::Signaled(tid, _, _)) if tid == self.pid() => break, // SIGKILL
+                        Ok(WaitStatus::Exited(tid, _)) if tid == self.pid() => {
+                            println!("Error in killing.");
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(_) => break,
                     }
                 }
             }
@@ -142,54 +1127,516 @@ impl Inferior {
         }
     }
 
-    /// Printing a backtrace
-    pub fn print_backtrace(&self, dwarf_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        // println!("%rip register: {:#x}", regs.rip);
-        let mut instruction_ptr = regs.rip as usize;
-        let mut base_ptr = regs.rbp as usize;
-        loop {
-            let line = dwarf_data.get_line_from_addr(instruction_ptr).unwrap();
-            let func = dwarf_data.get_function_from_addr(instruction_ptr).unwrap();
-            println!("{} ({}:{})", func, line.file, line.number);
-            if func == "main" {
-                break;
-            }
-            instruction_ptr = ptrace::read(self.pid(), (base_ptr+8) as ptrace::AddressType)? as usize;
-            base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as usize;
-        } 
-        Ok(())
-    }
-
     /// for writing breakpoints
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        self.write_byte_in(self.current, addr, val)
+    }
+
+    /// Writes a byte into the memory of the process `tid` belongs to, which need not be ours
+    fn write_byte_in(&self, tid: Pid, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let word = ptrace::read(tid, aligned_addr as ptrace::AddressType)? as u64;
         let orig_byte = (word >> 8 * byte_offset) & 0xff;
         let masked_word = word & !(0xff << 8 * byte_offset);
         let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
         ptrace::write(
-            self.pid(),
+            tid,
             aligned_addr as ptrace::AddressType,
             updated_word as *mut std::ffi::c_void,
         )?;
         Ok(orig_byte as u8)
     }
 
-    /// for changing rip
-    pub fn set_rip(&self, new_rip: usize) -> Result<(), nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        regs.rip = new_rip as u64;
-        ptrace::setregs(self.pid(), regs)?;
+    fn peek_user(&self, tid: Pid, offset: usize) -> Result<u64, nix::Error> {
+        let res = unsafe {
+            nix::errno::Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                tid.as_raw(),
+                offset as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if res == -1 && nix::errno::errno() != 0 {
+            return Err(nix::Error::last());
+        }
+        Ok(res as u64)
+    }
+
+    fn poke_user(&self, tid: Pid, offset: usize, value: u64) -> Result<(), nix::Error> {
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                tid.as_raw(),
+                offset as *mut libc::c_void,
+                value as *mut libc::c_void,
+            )
+        };
+        nix::errno::Errno::result(res).map(drop)
+    }
+
+    /// Programs DR0-DR3 of every thread with the given (address, length, access) slots and
+    /// enables them in DR7. Lengths must be 1, 2, 4 or 8 and addresses aligned to their length.
+    pub fn set_watch_slots(&mut self, slots: &[(usize, usize, WatchAccess)]) -> Result<(), nix::Error> {
+        self.watch_slots = slots.iter().take(WATCH_SLOTS).cloned().collect();
+        for tid in self.threads.iter().map(|thread| thread.tid).collect::<Vec<_>>() {
+            self.program_watch_slots(tid)?;
+        }
         Ok(())
     }
 
-    /// step
-    /// ptrace::step to go to next instruction
-    /// and waitpid for result
-    pub fn step(&self) -> Result<Status, nix::Error> {
-        ptrace::step(self.pid(), None)?;
-        self.wait(None)
+    fn program_watch_slots(&self, tid: Pid) -> Result<(), nix::Error> {
+        // DR7 has to be cleared first, since it is validated against the addresses
+        self.poke_user(tid, DEBUG_REGISTERS_OFFSET + 7 * 8, 0)?;
+        let mut dr7 = 0u64;
+        for (i, (addr, len, access)) in self.watch_slots.iter().enumerate() {
+            self.poke_user(tid, DEBUG_REGISTERS_OFFSET + i * 8, *addr as u64)?;
+            let rw = match access {
+                WatchAccess::Write => 0b01,
+                WatchAccess::ReadWrite => 0b11,
+            };
+            let len_bits = match len {
+                1 => 0b00,
+                2 => 0b01,
+                8 => 0b10,
+                _ => 0b11,
+            };
+            dr7 |= 1 << (2 * i);
+            dr7 |= (rw | len_bits << 2) << (16 + 4 * i);
+        }
+        self.poke_user(tid, DEBUG_REGISTERS_OFFSET + 7 * 8, dr7)
+    }
+
+    /// Returns the DR6 bits telling which watch slots fired in the current thread since the
+    /// last clear_watch_hits
+    pub fn watch_hits(&self) -> Result<u64, nix::Error> {
+        Ok(self.peek_user(self.current, DEBUG_REGISTERS_OFFSET + 6 * 8)? & 0xf)
+    }
+
+    /// Resets DR6 of the current thread, which the CPU never clears by itself
+    pub fn clear_watch_hits(&self) -> Result<(), nix::Error> {
+        self.poke_user(self.current, DEBUG_REGISTERS_OFFSET + 6 * 8, 0)
+    }
+
+    /// Changes a general purpose register of the current thread, given by its name as written
+    /// after `$`
+    pub fn set_register(&self, name: &str, value: u64) -> Result<(), nix::Error> {
+        self.set_thread_register(self.current, name, value)
+    }
+
+    fn set_thread_register(&self, tid: Pid, name: &str, value: u64) -> Result<(), nix::Error> {
+        let mut regs = ptrace::getregs(tid)?;
+        *register_field(&mut regs, name).ok_or(nix::Error::Sys(Errno::EINVAL))? = value;
+        ptrace::setregs(tid, regs)
+    }
+
+    /// Executes one instruction in the current thread with PTRACE_SINGLESTEP and waits until it
+    /// stops. The other threads stay stopped. The pending signal is delivered on the way.
+    /// Signals that should not stop the thread are announced, passed on or dropped according to
+    /// their policy, and the step is retried.
+    fn step(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        loop {
+            let current = self.current;
+            let status = match self.thread_mut(current).pending_signal.take() {
+                // Stepping into the signal handler would surprise the user. The handler runs
+                // to completion first, then the step is made.
+                Some(sig) => match self.run_signal_handler(sig, breakpoints)? {
+                    Some(status) => status,
+                    None => continue,
+                },
+                None => {
+                    self.step_thread(current)?;
+                    self.wait_thread(current, true, breakpoints)?
+                }
+            };
+            if let Some(status) = self.filter_signal(self.current, status) {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Resumes every thread as resume does and waits until one of them stops, which becomes
+    /// the current thread. The others are then stopped as well. Each thread gets its pending
+    /// signal on the way, and signals that should not stop the inferior are dealt with as in
+    /// step. A stop left over from last time is reported without resuming anything.
+    fn resume_all(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        if let Some(status) = self.take_pending_stop()? {
+            return Ok(status);
+        }
+        let resumed: Vec<(Pid, Option<signal::Signal>)> = self
+            .threads
+            .iter_mut()
+            .map(|thread| (thread.tid, thread.pending_signal.take()))
+            .collect();
+        for (tid, sig) in resumed {
+            match self.resume(tid, sig) {
+                // The thread is exiting, which is reported in a moment
+                Ok(()) | Err(nix::Error::Sys(Errno::ESRCH)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        loop {
+            let (tid, status) = match self.wait_event(None, false, breakpoints)? {
+                Some(event) => event,
+                None => continue,
+            };
+            match status {
+                Status::Stopped(_, _) | Status::Caught(_, _) => match self.filter_signal(tid, status) {
+                    Some(status) => {
+                        self.stop_threads(tid, breakpoints)?;
+                        self.current = tid;
+                        return Ok(status);
+                    }
+                    None => {
+                        let sig = self.thread_mut(tid).pending_signal.take();
+                        self.resume(tid, sig)?;
+                    }
+                },
+                // The process is gone, or exec did away with the other threads
+                _ => return Ok(status),
+            }
+        }
+    }
+
+    /// Deals with `creator` having forked `child`. The process that is not followed loses our
+    /// breakpoints and is detached or held, as the fork policy says. If `all_running` is set,
+    /// the followed process carries on. Otherwise, when following the parent, the creator is
+    /// resumed as in wait_event; the child is left stopped for wait_thread to resume. If the
+    /// catch policy asks to stop at the fork and all threads are running, the followed process
+    /// is left stopped instead, and the thread to report the stop for is returned.
+    fn follow_fork(&mut self, creator: Pid, child: Pid, vfork: bool, all_running: bool, single_step: bool, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<Pid>, nix::Error> {
+        let stop = all_running && if vfork { self.catch_policy.vfork } else { self.catch_policy.fork };
+        // The child starts out stopped, which may have been seen already
+        match self.early_threads.iter().position(|early| *early == child) {
+            Some(index) => {
+                self.early_threads.remove(index);
+            }
+            None => {
+                waitpid(child, Some(WaitPidFlag::__WALL))?;
+            }
+        }
+        let event = if vfork { "vfork" } else { "fork" };
+        if !self.fork_policy.follow_child {
+            let removed = self.remove_breakpoints_in(child, breakpoints);
+            if vfork {
+                // The child shares our memory until it calls exec or exits, and only then does
+                // the parent return from vfork
+                println!("[Detaching after vfork from child process {}]", child);
+                ptrace::detach(child, None)?;
+                ptrace::cont(creator, None)?;
+                while let WaitStatus::Stopped(_, sig) = waitpid(creator, Some(WaitPidFlag::__WALL))? {
+                    ptrace::cont(creator, Some(sig))?;
+                }
+                for addr in removed {
+                    self.write_byte_in(creator, addr, 0xcc)?;
+                }
+            } else {
+                self.let_go(vec![child], "child", event)?;
+            }
+            if stop {
+                return Ok(Some(creator));
+            }
+            if single_step {
+                self.step_thread(creator)?;
+            } else {
+                self.resume(creator, None)?;
+            }
+            return Ok(None);
+        }
+        println!("[Attaching after process {} {} to child process {}]", self.pid, event, child);
+        if all_running {
+            self.stop_threads(creator, breakpoints)?;
+        }
+        let parent: Vec<Pid> = self.threads.iter().map(|thread| thread.tid).collect();
+        self.pid = child;
+        if self.attached {
+            INTERRUPT_TARGET.store(child.as_raw(), Ordering::SeqCst);
+        }
+        self.threads = vec![Thread::new(1, child)];
+        self.next_thread_number = 2;
+        self.current = child;
+        self.reported = child;
+        self.program_watch_slots(child)?;
+        if vfork {
+            self.vfork_parent = parent;
+        } else {
+            self.remove_breakpoints_in(creator, breakpoints);
+            self.let_go(parent, "parent", event)?;
+        }
+        if stop {
+            return Ok(Some(child));
+        }
+        if all_running {
+            self.resume(child, None)?;
+        }
+        Ok(None)
+    }
+
+    /// Lets go of the parent of a vfork we followed into the child, now that the child no
+    /// longer shares its memory
+    fn release_vfork_parent(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        if self.vfork_parent.is_empty() {
+            return Ok(());
+        }
+        let parent = std::mem::take(&mut self.vfork_parent);
+        self.remove_breakpoints_in(parent[0], breakpoints);
+        self.let_go(parent, "parent", "vfork")
+    }
+
+    /// Detaches from or holds the threads of a process we no longer follow, its `relation` to
+    /// the one we do after `event`
+    fn let_go(&mut self, tids: Vec<Pid>, relation: &str, event: &str) -> Result<(), nix::Error> {
+        if self.fork_policy.detach {
+            println!("[Detaching after {} from {} process {}]", event, relation, tids[0]);
+            for tid in tids {
+                ptrace::detach(tid, None)?;
+            }
+        } else {
+            println!("[Holding {} process {} after {}]", relation, tids[0], event);
+            self.held.push(tids);
+        }
+        Ok(())
+    }
+
+    /// Puts the original bytes back in place of our breakpoints in the memory of the process
+    /// `tid` belongs to. Returns the addresses where there was a 0xcc to remove.
+    fn remove_breakpoints_in(&self, tid: Pid, breakpoints: &HashMap<usize, Breakpoint>) -> Vec<usize> {
+        let mut removed = Vec::new();
+        for (addr, bp) in breakpoints.iter() {
+            match self.write_byte_in(tid, *addr, bp.orig_byte) {
+                Ok(0xcc) => removed.push(*addr),
+                // A pending breakpoint, which was never installed
+                Ok(byte) => {
+                    let _ = self.write_byte_in(tid, *addr, byte);
+                }
+                Err(_) => {}
+            }
+        }
+        removed
+    }
+
+    /// Resumes a thread with PTRACE_SYSCALL if system calls are caught or traced, or else with
+    /// PTRACE_CONT, which does not report the exit of the system call it may be stopped in
+    fn resume(&mut self, tid: Pid, sig: Option<signal::Signal>) -> Result<(), nix::Error> {
+        if self.catch_policy.syscalls.is_some() || self.catch_policy.trace_syscalls {
+            return ptrace::syscall(tid, sig);
+        }
+        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+            thread.in_syscall = false;
+        }
+        ptrace::cont(tid, sig)
+    }
+
+    /// Single-steps a thread with PTRACE_SINGLESTEP. The exit of the system call it may be
+    /// stopped in shows up as an ordinary SIGTRAP.
+    fn step_thread(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+            thread.in_syscall = false;
+        }
+        ptrace::step(tid, None)
+    }
+
+    /// Waits until `tid`, the only thread running, stops. If the thread exits instead, the
+    /// remaining threads are resumed and the first of them to stop is reported.
+    fn wait_thread(&mut self, tid: Pid, single_step: bool, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        loop {
+            if let Some((_, status)) = self.wait_event(Some(tid), single_step, breakpoints)? {
+                return Ok(status);
+            }
+            if !self.is_thread(tid) {
+                return self.resume_all(breakpoints);
+            }
+        }
+    }
+
+    /// Applies the signal policy to a stop of thread `tid`. Returns None if the thread should
+    /// carry on, with the signal made pending if it is to be passed on.
+    fn filter_signal(&mut self, tid: Pid, status: Status) -> Option<Status> {
+        let sig = match status {
+            Status::Stopped(sig, _) if sig != signal::Signal::SIGTRAP => sig,
+            other => return Some(other),
+        };
+        let policy = self
+            .signal_policies
+            .get(&sig)
+            .cloned()
+            .unwrap_or_else(|| SignalPolicy::default_for(sig));
+        if policy.pass {
+            self.thread_mut(tid).pending_signal = Some(sig);
+        }
+        if policy.stop || self.catch_policy.signals.contains(&sig) {
+            return Some(status);
+        }
+        if policy.print {
+            println!("\nProgram received signal {}, {}.", sig, signal_description(sig));
+        }
+        None
+    }
+
+    /// Stops every thread but `tid`, which already is, with SIGSTOP. A thread may stop for
+    /// another reason first. If it hit a breakpoint, %rip is rewound so that it hits it again
+    /// once resumed, since the breakpoint may be gone by then. Other stops are kept to be
+    /// reported by the next resume_all.
+    fn stop_threads(&mut self, tid: Pid, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        let others: Vec<Pid> = self.threads.iter().map(|thread| thread.tid).filter(|other| *other != tid).collect();
+        for other in others {
+            // A fork may have made us follow another process meanwhile
+            if !self.is_thread(other) {
+                continue;
+            }
+            // A SIGSTOP still on its way would swallow another one
+            let stray = std::mem::replace(&mut self.thread_mut(other).stray_sigstop, false);
+            if !stray && tgkill(self.pid, other, signal::Signal::SIGSTOP).is_err() {
+                // It is exiting, which is reported later
+                continue;
+            }
+            loop {
+                let status = match self.wait_event(Some(other), false, breakpoints)? {
+                    Some((_, status)) => status,
+                    None if self.is_thread(other) => continue,
+                    None => break,
+                };
+                match status {
+                    Status::Stopped(signal::Signal::SIGSTOP, _) => break,
+                    Status::Stopped(signal::Signal::SIGTRAP, rip) if breakpoints.contains_key(&(rip - 1)) => {
+                        self.set_thread_register(other, "rip", (rip - 1) as u64)?;
+                    }
+                    // Resuming the thread would run the system call before the stop is
+                    // reported, so the SIGSTOP is left to arrive later
+                    Status::Caught(_, _) => {
+                        let thread = self.thread_mut(other);
+                        thread.pending_stop = Some(status);
+                        thread.stray_sigstop = true;
+                        break;
+                    }
+                    Status::Stopped(_, _) => {
+                        if let Some(status) = self.filter_signal(other, status) {
+                            self.thread_mut(other).pending_stop = Some(status);
+                        }
+                    }
+                    // The whole process is gone, or replaced by exec
+                    _ => return Ok(()),
+                }
+                // Collect the SIGSTOP
+                ptrace::cont(other, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the first thread with a stop left over from stop_threads current and returns the
+    /// stop
+    fn take_pending_stop(&mut self) -> Result<Option<Status>, nix::Error> {
+        let (tid, status) = match self.threads.iter_mut().find(|thread| thread.pending_stop.is_some()) {
+            Some(thread) => (thread.tid, thread.pending_stop.take().unwrap()),
+            None => return Ok(None),
+        };
+        self.current = tid;
+        Ok(Some(status))
+    }
+
+    /// Delivers `sig` to the current thread and runs until its handler returns to the current
+    /// instruction. Returns None once it has, or how the inferior stopped before that.
+    fn run_signal_handler(&mut self, sig: signal::Signal, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<Status>, nix::Error> {
+        let current = self.current;
+        let rip = self.getregs()?.rip as usize;
+        let orig_byte = self.write_byte(rip, 0xcc)?;
+        self.thread_mut(current).in_syscall = false;
+        ptrace::cont(current, Some(sig))?;
+        let status = self.wait_thread(current, false, breakpoints)?;
+        if let Status::Stopped(_, _) = status {
+            self.write_byte(rip, orig_byte)?;
+        }
+        match status {
+            Status::Stopped(signal::Signal::SIGTRAP, addr) if addr == rip + 1 && self.current == current => {
+                self.set_register("rip", rip as u64)?;
+                Ok(None)
+            }
+            other => Ok(Some(other)),
+        }
+    }
+
+    /// Reads memory with a single process_vm_readv call. Returns None if that fails or comes up
+    /// short, e.g. for pages without read permission, which ptrace can still read.
+    fn read_memory_vm(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        let read = process_vm_readv(
+            self.current,
+            &[IoVec::from_mut_slice(&mut bytes)],
+            &[RemoteIoVec { base: addr, len }],
+        )
+        .ok()?;
+        if read == len {
+            Some(bytes)
+        } else {
+            None
+        }
+    }
+
+    /// Reads memory a word at a time with PTRACE_PEEKDATA
+    fn read_memory_words(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < addr + len {
+            let word = self.read_word(word_addr)?;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            word_addr += size_of::<usize>();
+        }
+        let start = addr - align_addr_to_word(addr);
+        Ok(bytes[start..start + len].to_vec())
+    }
+}
+
+impl Drop for Inferior {
+    fn drop(&mut self) {
+        // A process taken over from this one may have become the target already
+        let _ = INTERRUPT_TARGET.compare_exchange(
+            self.pid.as_raw(),
+            0,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+}
+
+impl ProcessState for Inferior {
+    fn getregs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.current)
+    }
+
+    /// Reads the x87/SSE register state. nix has no wrapper for PTRACE_GETFPREGS.
+    fn getfpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        let mut fpregs = std::mem::MaybeUninit::<libc::user_fpregs_struct>::uninit();
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.current.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                fpregs.as_mut_ptr(),
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        Ok(unsafe { fpregs.assume_init() })
+    }
+
+    /// Reads `len` bytes starting at `addr`, showing the original bytes in place of our 0xcc
+    fn read_memory(&self, addr: usize, len: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = match self.read_memory_vm(addr, len) {
+            Some(bytes) => bytes,
+            None => self.read_memory_words(addr, len)?,
+        };
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            if let Some(bp) = breakpoints.get(&(addr + i)) {
+                *byte = bp.orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a word from the inferior's memory
+    fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        Ok(ptrace::read(self.current, addr as ptrace::AddressType)? as usize)
     }
 }
+
@@ -0,0 +1,130 @@
+//! Finding out where the executable and its shared libraries are loaded in the inferior's
+//! address space
+
+use crate::dwarf_data::{read_unsigned, DwarfData};
+use crate::inferior::{Breakpoint, ProcessState};
+use std::collections::HashMap;
+
+/// Auxiliary vector entry holding the program's entry point
+const AT_ENTRY: u64 = 9;
+/// Dynamic section entries
+const DT_NULL: u64 = 0;
+const DT_DEBUG: u64 = 21;
+/// `r_debug.r_state` while the list of libraries is not being changed
+const RT_CONSISTENT: u64 = 0;
+
+/// Offsets into the 64-bit `struct r_debug` and `struct link_map` of <link.h>
+const R_MAP_OFFSET: usize = 8;
+const R_BRK_OFFSET: usize = 16;
+const R_STATE_OFFSET: usize = 24;
+const L_ADDR_OFFSET: usize = 0;
+const L_NAME_OFFSET: usize = 8;
+const L_NEXT_OFFSET: usize = 24;
+
+/// Gives up on lists longer than this, in case the link map is corrupt
+const MAX_LIBRARIES: usize = 1024;
+const MAX_PATH_LENGTH: usize = 4096;
+
+/// A shared library mapped into the inferior, with its addresses relocated
+pub struct SharedLibrary {
+    pub path: String,
+    pub dwarf_data: DwarfData,
+}
+
+impl SharedLibrary {
+    /// Tells whether `name` refers to this library: its file name, or the file name without the
+    /// version suffix, e.g. `libc.so` for `/lib/x86_64-linux-gnu/libc.so.6`
+    pub fn matches(&self, name: &str) -> bool {
+        let file_name = self.path.rsplit('/').next().unwrap();
+        file_name == name || file_name.starts_with(&format!("{}.", name))
+    }
+}
+
+/// What the dynamic loader tells us through `r_debug`
+pub struct LinkMap {
+    /// Address the loader calls whenever it is about to change the list of libraries and
+    /// once it is done
+    pub r_brk: usize,
+    /// False while a library is being mapped or unmapped
+    pub consistent: bool,
+    /// Path and load bias of every shared library. The executable and the vDSO are left out.
+    pub libraries: Vec<(String, usize)>,
+}
+
+/// Returns the entry point recorded in an auxiliary vector, e.g. `/proc/<pid>/auxv` or a core
+/// dump's NT_AUXV note
+pub fn auxv_entry(auxv: &[u8]) -> Option<usize> {
+    auxv.chunks_exact(16)
+        .find(|entry| read_unsigned(&entry[..8]) == AT_ENTRY)
+        .map(|entry| read_unsigned(&entry[8..]) as usize)
+}
+
+/// Returns the entry point of a live process
+pub fn process_entry(pid: i32) -> Option<usize> {
+    auxv_entry(&std::fs::read(format!("/proc/{}/auxv", pid)).ok()?)
+}
+
+/// Follows the executable's DT_DEBUG entry to the loader's `r_debug` and walks its list of
+/// libraries. `dynamic_section` is the relocated address of the executable's .dynamic section.
+/// Returns None until the loader has filled DT_DEBUG in, and for static executables.
+pub fn read_link_map(
+    process: &dyn ProcessState,
+    dynamic_section: usize,
+    breakpoints: &HashMap<usize, Breakpoint>,
+) -> Option<LinkMap> {
+    let read_word = |addr: usize| {
+        process
+            .read_memory(addr, 8, breakpoints)
+            .ok()
+            .map(|bytes| read_unsigned(&bytes) as usize)
+    };
+    let mut r_debug = 0;
+    for entry in (dynamic_section..).step_by(16) {
+        match read_word(entry)? as u64 {
+            DT_NULL => break,
+            DT_DEBUG => {
+                r_debug = read_word(entry + 8)?;
+                break;
+            }
+            _ => {}
+        }
+    }
+    if r_debug == 0 {
+        return None;
+    }
+
+    let mut libraries = Vec::new();
+    let mut link_map = read_word(r_debug + R_MAP_OFFSET)?;
+    while link_map != 0 && libraries.len() < MAX_LIBRARIES {
+        let load_bias = read_word(link_map + L_ADDR_OFFSET)?;
+        let path = read_word(link_map + L_NAME_OFFSET)
+            .and_then(|name| read_path(&read_word, name))
+            .unwrap_or_default();
+        // The executable's own entry has no name, and the vDSO's name is not a file
+        if std::path::Path::new(&path).is_file() {
+            libraries.push((path, load_bias));
+        }
+        link_map = read_word(link_map + L_NEXT_OFFSET)?;
+    }
+    Some(LinkMap {
+        r_brk: read_word(r_debug + R_BRK_OFFSET)?,
+        consistent: read_word(r_debug + R_STATE_OFFSET)? as u64 == RT_CONSISTENT,
+        libraries,
+    })
+}
+
+/// Reads a NUL-terminated path a word at a time
+fn read_path(read_word: &dyn Fn(usize) -> Option<usize>, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_PATH_LENGTH {
+        let word = read_word(addr + bytes.len())?.to_le_bytes();
+        match word.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&word[..end]);
+                return Some(String::from_utf8_lossy(&bytes).into_owned());
+            }
+            None => bytes.extend_from_slice(&word),
+        }
+    }
+    None
+}
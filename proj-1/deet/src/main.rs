@@ -1,24 +1,58 @@
+mod core_file;
 mod debugger;
 mod debugger_command;
+mod disassembler;
 mod inferior;
 mod dwarf_data;
+mod expr;
 mod gimli_wrapper;
+mod link_map;
+mod syscalls;
 
-use crate::debugger::Debugger;
-use nix::sys::signal::{signal, SigHandler, Signal};
+use crate::debugger::{process_executable, Debugger};
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+    let trace = args.get(1).map(|arg| arg.as_str()) == Some("--trace-syscalls");
+    let pid = if trace && args.len() >= 3 {
+        None
+    } else if args.len() == 3 && args[1] == "--pid" {
+        match args[2].parse::<i32>() {
+            Ok(pid) => Some(pid),
+            Err(_) => {
+                println!("Invalid pid {}", args[2]);
+                std::process::exit(1);
+            }
+        }
+    } else if !trace && (args.len() == 2 || args.len() == 3) {
+        None
+    } else {
+        println!("Usage: {} <target program> [core file]", args[0]);
+        println!("       {} --pid <pid>", args[0]);
+        println!("       {} --trace-syscalls <target program> [args...]", args[0]);
         std::process::exit(1);
-    }
-    let target = &args[1];
+    };
 
-    // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
-    // processes)
-    unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
+    // ctrl+c interrupts the inferior rather than deet
+    inferior::install_interrupt_handler().expect("Error installing the SIGINT handler");
 
-    Debugger::new(target).run();
+    match pid {
+        Some(pid) => {
+            let mut debugger = Debugger::new(&process_executable(pid));
+            debugger.attach(pid);
+            debugger.run();
+        }
+        None if trace => {
+            let mut debugger = Debugger::new(&args[2]);
+            debugger.trace(&args[3..]);
+        }
+        None => {
+            let mut debugger = Debugger::new(&args[1]);
+            if let Some(core_path) = args.get(2) {
+                debugger.load_core(core_path);
+            }
+            debugger.run();
+        }
+    }
 }